@@ -0,0 +1,88 @@
+use axum::http::{HeaderMap, HeaderValue, Method, Version};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lambda_web_gateway::config::{Config, PayloadFormat};
+use lambda_web_gateway::event::{self, HttpRequest};
+use lambda_web_gateway::router::{RouteLookup, RouteTable};
+use std::collections::HashMap;
+
+fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in [
+        ("host", "api.example.com"),
+        ("user-agent", "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36"),
+        ("accept", "application/json"),
+        ("accept-encoding", "gzip, deflate, br"),
+        ("content-type", "application/json"),
+        ("x-forwarded-for", "198.51.100.7"),
+        ("x-request-id", "0f2b6c1e-4c3a-4f7e-9a55-2b0f0c5f3d11"),
+    ] {
+        headers.insert(name, HeaderValue::from_static(value));
+    }
+    headers.append("cookie", HeaderValue::from_static("session=abc123"));
+    headers.append("cookie", HeaderValue::from_static("theme=dark"));
+    headers
+}
+
+/// Serializes the event of a small JSON request and of a 4 KiB binary upload in every
+/// built-in payload format.
+fn build_event(c: &mut Criterion) {
+    let headers = headers();
+    let mut binary_headers = headers.clone();
+    binary_headers.insert("content-type", HeaderValue::from_static("application/octet-stream"));
+    let query = vec![
+        ("page".to_string(), "2".to_string()),
+        ("tag".to_string(), "a".to_string()),
+        ("tag".to_string(), "b".to_string()),
+    ];
+    let path_parameters = HashMap::from([("id".to_string(), "42".to_string())]);
+    let json_body = br#"{"name":"widget","quantity":3,"tags":["a","b"]}"#.to_vec();
+    let binary_body: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+
+    let mut group = c.benchmark_group("build_event");
+    for (format, multi_value_headers) in [
+        (PayloadFormat::Alb, false),
+        (PayloadFormat::Alb, true),
+        (PayloadFormat::ApiGatewayV1, false),
+        (PayloadFormat::ApiGatewayV2, false),
+    ] {
+        let config = Config {
+            lambda_function_name: "function".to_string(),
+            payload_format: format.clone(),
+            multi_value_headers,
+            ..Config::default()
+        };
+        let table = RouteTable::new(&config).unwrap();
+        let RouteLookup::Matched(route) = table.find(&Method::POST, "/items/42") else {
+            unreachable!()
+        };
+        let name = format!("{:?}{}", format, if multi_value_headers { "MultiValue" } else { "" });
+
+        for (body_name, headers, body) in [
+            ("json", &headers, &json_body),
+            ("binary_4k", &binary_headers, &binary_body),
+        ] {
+            let request = HttpRequest {
+                request_id: "0f2b6c1e-4c3a-4f7e-9a55-2b0f0c5f3d11",
+                method: &Method::POST,
+                version: Version::HTTP_11,
+                path: "/items/42",
+                raw_path: "/items/42",
+                headers,
+                raw_query: "page=2&tag=a&tag=b",
+                query: &query,
+                body,
+                source_ip: Some("198.51.100.7".parse().unwrap()),
+                api_key: None,
+                resource: Some("/items/{id}"),
+                path_parameters: &path_parameters,
+            };
+            group.bench_with_input(BenchmarkId::new(name.clone(), body_name), &request, |b, request| {
+                b.iter(|| event::build_event(route.target, request, &config).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, build_event);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use axum::body::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use http_body_util::BodyExt;
+use lambda_web_gateway::body;
+
+const TOTAL: usize = 8 * 1024 * 1024;
+
+/// Moves 8 MiB through the streaming response body in chunks of various sizes, with the
+/// default and a small buffer, to show the per-chunk overhead of the pipeline.
+fn body_channel(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("body_channel");
+    group.throughput(Throughput::Bytes(TOTAL as u64));
+
+    for buffer_size in [16 * 1024, 64 * 1024] {
+        for chunk_size in [1024, 16 * 1024, 128 * 1024] {
+            let chunk = Bytes::from(vec![b'x'; chunk_size]);
+            group.bench_with_input(
+                BenchmarkId::new(
+                    format!("buffer_{}k", buffer_size / 1024),
+                    format!("chunk_{}k", chunk_size / 1024),
+                ),
+                &chunk,
+                |b, chunk| {
+                    b.to_async(&runtime).iter(|| async {
+                        let (tx, mut body) = body::channel(buffer_size);
+                        let chunk = chunk.clone();
+                        let producer = tokio::spawn(async move {
+                            for _ in 0..TOTAL / chunk.len() {
+                                tx.send_data(chunk.clone()).await.unwrap();
+                            }
+                        });
+                        let mut received = 0;
+                        while let Some(frame) = body.frame().await {
+                            received += frame.unwrap().into_data().unwrap().len();
+                        }
+                        producer.await.unwrap();
+                        assert_eq!(received, TOTAL);
+                    });
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, body_channel);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+use crate::error::GatewayError;
+use axum::body::{Body, Bytes};
+use futures_util::StreamExt;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use std::sync::Arc;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+type Item = (Result<Frame<Bytes>, GatewayError>, Option<OwnedSemaphorePermit>);
+
+/// The HTTP client went away and dropped the response body.
+#[derive(Debug)]
+pub struct ClientDisconnected;
+
+/// Creates a response body fed through the returned sender.
+///
+/// At most `capacity` bytes of data are buffered between the sender and the connection; a
+/// frame larger than that is still sent, on its own. Frames are passed on as they are, so
+/// sending `Bytes` does not copy them.
+pub fn channel(capacity: usize) -> (BodySender, Body) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let capacity = capacity.clamp(1, Semaphore::MAX_PERMITS);
+    let sender = BodySender {
+        tx,
+        budget: Arc::new(Semaphore::new(capacity)),
+        capacity,
+    };
+    // The permit is released once the connection takes the frame.
+    let frames = UnboundedReceiverStream::new(rx).map(|(frame, _permit): Item| frame);
+    (sender, Body::new(StreamBody::new(frames)))
+}
+
+#[derive(Debug)]
+pub struct BodySender {
+    tx: mpsc::UnboundedSender<Item>,
+    budget: Arc<Semaphore>,
+    capacity: usize,
+}
+
+impl BodySender {
+    /// Sends a frame, waiting while the buffer is full. An `Err` aborts the response.
+    pub async fn send(&self, frame: Result<Frame<Bytes>, GatewayError>) -> Result<(), ClientDisconnected> {
+        if self.tx.is_closed() {
+            return Err(ClientDisconnected);
+        }
+        let len = match &frame {
+            Ok(frame) => frame.data_ref().map_or(0, Bytes::len),
+            Err(_) => 0,
+        };
+        let permit = match len.min(self.capacity) {
+            0 => None,
+            n => {
+                let acquire = self.budget.clone().acquire_many_owned(n as u32);
+                let permit = tokio::select! {
+                    permit = acquire => permit.map_err(|_| ClientDisconnected)?,
+                    _ = self.tx.closed() => return Err(ClientDisconnected),
+                };
+                Some(permit)
+            }
+        };
+        self.tx.send((frame, permit)).map_err(|_| ClientDisconnected)
+    }
+
+    pub async fn send_data(&self, data: Bytes) -> Result<(), ClientDisconnected> {
+        self.send(Ok(Frame::data(data))).await
+    }
+
+    /// Completes when the response body has been dropped.
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("body_tests.rs");
+}
@@ -0,0 +1,48 @@
+use super::*;
+use http_body_util::BodyExt;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_channel_passes_frames() {
+    let (tx, body) = channel(1024);
+    tx.send_data(Bytes::from_static(b"hello ")).await.unwrap();
+    tx.send_data(Bytes::from_static(b"world")).await.unwrap();
+    drop(tx);
+
+    let collected = body.collect().await.unwrap().to_bytes();
+    assert_eq!(collected, "hello world");
+}
+
+#[tokio::test]
+async fn test_channel_limits_buffered_bytes() {
+    let (tx, mut body) = channel(8);
+    tx.send_data(Bytes::from_static(b"12345")).await.unwrap();
+
+    // The budget has 3 bytes left, so the next chunk waits for the body to be read.
+    let send = tx.send_data(Bytes::from_static(b"6789"));
+    tokio::pin!(send);
+    assert!(tokio::time::timeout(Duration::from_millis(20), &mut send).await.is_err());
+
+    let frame = body.frame().await.unwrap().unwrap();
+    assert_eq!(frame.into_data().unwrap(), "12345");
+    send.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_channel_sends_oversized_frame() {
+    let (tx, body) = channel(4);
+    tx.send_data(Bytes::from_static(b"larger than the buffer")).await.unwrap();
+    drop(tx);
+
+    assert_eq!(body.collect().await.unwrap().to_bytes(), "larger than the buffer");
+}
+
+#[tokio::test]
+async fn test_channel_client_disconnected() {
+    let (tx, body) = channel(4);
+    tx.send_data(Bytes::from_static(b"full")).await.unwrap();
+    drop(body);
+
+    assert!(tx.send_data(Bytes::from_static(b"more")).await.is_err());
+    tokio::time::timeout(Duration::from_millis(20), tx.closed()).await.unwrap();
+}
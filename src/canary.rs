@@ -0,0 +1,150 @@
+use crate::config::RouteConfig;
+use crate::router::LambdaTarget;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashSet;
+
+/// Response header naming the variant that served a request.
+pub const VARIANT_HEADER: &str = "x-gateway-variant";
+
+/// Qualifiers of a route's function that share its traffic by weight.
+#[derive(Clone, Debug)]
+pub struct Canary {
+    variants: Vec<Variant>,
+    total_weight: u64,
+    sticky: Option<Sticky>,
+}
+
+/// One of the qualifiers of a [`Canary`].
+#[derive(Clone, Debug)]
+pub struct Variant {
+    /// The qualifier, as reported in the `x-gateway-variant` header and the metrics.
+    pub name: String,
+    pub header_value: HeaderValue,
+    pub weight: u32,
+    pub target: LambdaTarget,
+}
+
+/// Where the key pinning a client to a variant is read from.
+#[derive(Clone, Debug)]
+enum Sticky {
+    Header(HeaderName),
+    Cookie(String),
+}
+
+impl Canary {
+    /// The variants of `route`, each invoking `target` with its qualifier. `None` when the route
+    /// has no variants.
+    pub(crate) fn from_config(route: &RouteConfig, target: &LambdaTarget) -> Result<Option<Self>, String> {
+        if route.variants.is_empty() {
+            return match route.sticky {
+                Some(_) => Err(format!("sticky requires variants for route {}", route.path)),
+                None => Ok(None),
+            };
+        }
+        if route.qualifier.is_some() {
+            return Err(format!("Set either qualifier or variants for route {}", route.path));
+        }
+
+        let mut names = HashSet::new();
+        let mut variants = Vec::with_capacity(route.variants.len());
+        for variant in &route.variants {
+            if !names.insert(variant.qualifier.as_str()) {
+                return Err(format!(
+                    "Variant {} listed more than once for route {}",
+                    variant.qualifier, route.path
+                ));
+            }
+            let header_value = HeaderValue::from_str(&variant.qualifier).map_err(|_| {
+                format!(
+                    "Invalid variant qualifier for route {}: {}",
+                    route.path, variant.qualifier
+                )
+            })?;
+            variants.push(Variant {
+                name: variant.qualifier.clone(),
+                header_value,
+                weight: variant.weight,
+                target: LambdaTarget {
+                    qualifier: Some(variant.qualifier.clone()),
+                    ..target.clone()
+                },
+            });
+        }
+        let total_weight = variants.iter().map(|v| u64::from(v.weight)).sum();
+        if total_weight == 0 {
+            return Err(format!("Variants of route {} have no weight", route.path));
+        }
+
+        let sticky = match &route.sticky {
+            None => None,
+            Some(sticky) => match (&sticky.header, &sticky.cookie) {
+                (Some(header), None) => {
+                    Some(Sticky::Header(HeaderName::try_from(header.as_str()).map_err(|_| {
+                        format!("Invalid sticky header for route {}: {}", route.path, header)
+                    })?))
+                }
+                (None, Some(cookie)) => Some(Sticky::Cookie(cookie.clone())),
+                _ => {
+                    return Err(format!(
+                        "Set one of header or cookie in sticky for route {}",
+                        route.path
+                    ))
+                }
+            },
+        };
+
+        Ok(Some(Canary {
+            variants,
+            total_weight,
+            sticky,
+        }))
+    }
+
+    pub fn variants(&self) -> &[Variant] {
+        &self.variants
+    }
+
+    /// Picks the variant of a request. Requests with the same sticky key get the same variant
+    /// for as long as the weights stay the same; others are picked at random.
+    pub fn select(&self, headers: &HeaderMap) -> &Variant {
+        let mut point = match self.sticky_key(headers) {
+            Some(key) => fnv1a(key.as_bytes()) % self.total_weight,
+            None => fastrand::u64(..self.total_weight),
+        };
+        for variant in &self.variants {
+            if point < u64::from(variant.weight) {
+                return variant;
+            }
+            point -= u64::from(variant.weight);
+        }
+        unreachable!("the point is below the total weight")
+    }
+
+    fn sticky_key<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        match self.sticky.as_ref()? {
+            Sticky::Header(name) => headers.get(name)?.to_str().ok(),
+            Sticky::Cookie(name) => headers
+                .get_all("cookie")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(';'))
+                .find_map(|cookie| {
+                    let (key, value) = cookie.trim().split_once('=')?;
+                    (key == name).then_some(value)
+                }),
+        }
+        .filter(|key| !key.is_empty())
+    }
+}
+
+/// 64-bit FNV-1a, so that a key maps to the same variant on every gateway instance.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    include!("canary_tests.rs");
+}
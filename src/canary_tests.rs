@@ -0,0 +1,130 @@
+use super::*;
+use crate::config::{PayloadFormat, StickyConfig, VariantConfig};
+
+fn target() -> LambdaTarget {
+    LambdaTarget {
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..LambdaTarget::for_test("function")
+    }
+}
+
+fn route(variants: &[(&str, u32)], sticky: Option<StickyConfig>) -> RouteConfig {
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: function}").unwrap();
+    route.variants = variants
+        .iter()
+        .map(|(qualifier, weight)| VariantConfig {
+            qualifier: qualifier.to_string(),
+            weight: *weight,
+        })
+        .collect();
+    route.sticky = sticky;
+    route
+}
+
+fn canary(variants: &[(&str, u32)], sticky: Option<StickyConfig>) -> Canary {
+    Canary::from_config(&route(variants, sticky), &target()).unwrap().unwrap()
+}
+
+fn header(name: &str) -> Option<StickyConfig> {
+    Some(StickyConfig {
+        header: Some(name.to_string()),
+        cookie: None,
+    })
+}
+
+fn headers(name: &'static str, value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, value.parse().unwrap());
+    headers
+}
+
+#[test]
+fn test_from_config() {
+    assert!(Canary::from_config(&route(&[], None), &target()).unwrap().is_none());
+
+    let canary = canary(&[("blue", 90), ("green", 10)], None);
+    let qualifiers: Vec<_> = canary.variants().iter().map(|v| v.target.qualifier.as_deref()).collect();
+    assert_eq!(qualifiers, vec![Some("blue"), Some("green")]);
+    assert_eq!(canary.variants()[1].header_value, "green");
+}
+
+#[test]
+fn test_from_config_invalid() {
+    let error = |route: RouteConfig| Canary::from_config(&route, &target()).unwrap_err();
+
+    assert_eq!(
+        error(route(&[], header("x-user-id"))),
+        "sticky requires variants for route /orders"
+    );
+    let mut qualified = route(&[("1", 1)], None);
+    qualified.qualifier = Some("live".to_string());
+    assert_eq!(error(qualified), "Set either qualifier or variants for route /orders");
+    assert_eq!(
+        error(route(&[("1", 1), ("1", 2)], None)),
+        "Variant 1 listed more than once for route /orders"
+    );
+    assert_eq!(error(route(&[("1", 0), ("2", 0)], None)), "Variants of route /orders have no weight");
+    assert_eq!(
+        error(route(&[("1", 1)], header("not a header"))),
+        "Invalid sticky header for route /orders: not a header"
+    );
+    let both = StickyConfig {
+        header: Some("x-user-id".to_string()),
+        cookie: Some("session".to_string()),
+    };
+    assert_eq!(
+        error(route(&[("1", 1)], Some(both))),
+        "Set one of header or cookie in sticky for route /orders"
+    );
+}
+
+#[test]
+fn test_select_by_weight() {
+    let canary = canary(&[("off", 0), ("blue", 1), ("green", 1)], None);
+    let mut selected = HashSet::new();
+    for _ in 0..200 {
+        selected.insert(canary.select(&HeaderMap::new()).name.as_str());
+    }
+    assert_eq!(selected, HashSet::from(["blue", "green"]));
+}
+
+#[test]
+fn test_select_sticky_header() {
+    let canary = canary(&[("blue", 50), ("green", 50)], header("x-user-id"));
+
+    for user in ["alice", "bob", "carol"] {
+        let variant = &canary.select(&headers("x-user-id", user)).name;
+        for _ in 0..20 {
+            assert_eq!(&canary.select(&headers("x-user-id", user)).name, variant);
+        }
+    }
+}
+
+#[test]
+fn test_select_sticky_cookie() {
+    let sticky = StickyConfig {
+        header: None,
+        cookie: Some("session".to_string()),
+    };
+    let canary = canary(&[("blue", 50), ("green", 50)], Some(sticky));
+
+    let variant = &canary.select(&headers("cookie", "theme=dark; session=abc123")).name;
+    for _ in 0..20 {
+        assert_eq!(&canary.select(&headers("cookie", "session=abc123")).name, variant);
+    }
+    assert_eq!(canary.sticky_key(&headers("cookie", "theme=dark; session=")), None);
+    assert_eq!(canary.sticky_key(&headers("cookie", "session2=x")), None);
+}
+
+#[test]
+fn test_sticky_clients_stay_on_a_growing_variant() {
+    let before = canary(&[("blue", 90), ("green", 10)], header("x-user-id"));
+    let after = canary(&[("blue", 80), ("green", 20)], header("x-user-id"));
+
+    for user in 0..1000 {
+        let headers = headers("x-user-id", &user.to_string());
+        if before.select(&headers).name == "green" {
+            assert_eq!(after.select(&headers).name, "green");
+        }
+    }
+}
@@ -1,11 +1,13 @@
+use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub lambda_function_name: String,
     #[serde(default = "default_lambda_invoke_mode")]
     pub lambda_invoke_mode: LambdaInvokeMode,
@@ -15,6 +17,359 @@ pub struct Config {
     pub auth_mode: AuthMode,
     #[serde(default = "default_addr")]
     pub addr: String,
+    /// Address of a separate listener serving Prometheus metrics on `/metrics`. Metrics are not
+    /// served when not set.
+    #[serde(default)]
+    pub metrics_addr: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default)]
+    pub virtual_hosts: Vec<VirtualHostConfig>,
+    #[serde(default)]
+    pub payload_format: PayloadFormat,
+    /// Sends `multiValueHeaders` and `multiValueQueryStringParameters` in ALB events.
+    #[serde(default)]
+    pub multi_value_headers: bool,
+    /// Content type of responses in the `Raw` payload format.
+    #[serde(default = "default_raw_content_type")]
+    pub raw_content_type: String,
+    /// Media types whose bodies are always base64 encoded. Supports `*` wildcards.
+    #[serde(default)]
+    pub binary_media_types: Vec<String>,
+    /// Media types whose bodies are sent as text when they are valid UTF-8.
+    #[serde(default = "default_text_media_types")]
+    pub text_media_types: Vec<String>,
+    /// Template rendering the event in the `Template` payload format.
+    #[serde(default)]
+    pub request_template: Option<String>,
+    /// Template mapping the function result to `statusCode`, `headers` and `body`.
+    #[serde(default)]
+    pub response_template: Option<String>,
+    /// Stage name reported in API Gateway events.
+    #[serde(default = "default_stage")]
+    pub stage: String,
+    /// Stage variables reported in API Gateway events.
+    #[serde(default)]
+    pub stage_variables: HashMap<String, String>,
+    /// HTTP responses for function errors, matched in order against the error's `errorType`.
+    #[serde(default)]
+    pub error_mappings: Vec<ErrorMapping>,
+    /// Includes the function's `errorMessage` and `stackTrace` in error responses.
+    #[serde(default)]
+    pub show_stack_traces: bool,
+    /// How `ResponseStream` responses are checked for a metadata prelude.
+    #[serde(default)]
+    pub prelude_detection: PreludeDetection,
+    /// Largest metadata prelude accepted, in bytes.
+    #[serde(default = "default_max_prelude_size")]
+    pub max_prelude_size: usize,
+    /// Bytes of a streamed response buffered ahead of the client before reading from Lambda pauses.
+    #[serde(default = "default_stream_buffer_size")]
+    pub stream_buffer_size: usize,
+    /// Seconds a streamed response may go without data before it is ended.
+    #[serde(default)]
+    pub stream_idle_timeout: Option<u64>,
+    /// Seconds a streamed response may run in total before it is ended.
+    #[serde(default)]
+    pub stream_max_duration: Option<u64>,
+    /// Streams responses as Server-Sent Events, flushed on event boundaries.
+    #[serde(default)]
+    pub sse: bool,
+    /// Seconds of silence after which a `:keepalive` comment is sent on SSE responses; 0 disables it.
+    #[serde(default = "default_sse_keepalive_interval")]
+    pub sse_keepalive_interval: u64,
+    /// Ends failed response streams with an `x-lambda-error-code` trailer instead of aborting
+    /// them, for clients that send `TE: trailers`.
+    #[serde(default)]
+    pub stream_error_trailers: bool,
+    /// Settings of the AWS SDK client invoking functions.
+    #[serde(default)]
+    pub lambda_client: LambdaClientConfig,
+    /// Regions to invoke functions in, in order of priority. An invocation that is throttled or
+    /// finds Lambda unavailable is retried in the next region.
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// Consecutive failures after which a region is skipped.
+    #[serde(default = "default_failover_ejection_threshold")]
+    pub failover_ejection_threshold: u32,
+    /// Seconds a region is skipped for after `failover_ejection_threshold` failures.
+    #[serde(default = "default_failover_ejection_duration")]
+    pub failover_ejection_duration: u64,
+    /// Where functions are invoked.
+    #[serde(default)]
+    pub backend: Backend,
+    /// The handler executable run by the `Local` backend.
+    #[serde(default)]
+    pub local_runtime: Option<LocalRuntimeConfig>,
+}
+
+/// Maps function errors whose `errorType` matches `error_type` to an HTTP response.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ErrorMapping {
+    /// `errorType` pattern, where `*` matches any run of characters.
+    pub error_type: String,
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: StatusCode,
+    /// Response body. The default JSON error body is sent when not set.
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default = "default_error_content_type")]
+    pub content_type: String,
+}
+
+/// Settings of the AWS SDK client invoking functions. Unset fields fall back to the settings of
+/// the enclosing virtual host or the top level, and then to the SDK's defaults, which read the
+/// usual `AWS_*` environment variables and shared config files.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct LambdaClientConfig {
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Profile in the shared AWS config and credentials files.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Lambda endpoint, e.g. a local stand-in such as `http://localhost:9001`.
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Seconds to wait for a connection to be established.
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for the first byte of a response. Must exceed the function's run time
+    /// for buffered invocations.
+    #[serde(default)]
+    pub read_timeout: Option<u64>,
+    #[serde(default)]
+    pub retry_mode: Option<RetryMode>,
+    /// Attempts per invocation, including the first one.
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// Idle connections kept open to the Lambda endpoint.
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Role assumed to invoke functions, e.g. in another account.
+    #[serde(default)]
+    pub assume_role: Option<AssumeRoleConfig>,
+}
+
+/// A qualifier of a route's function and its share of the route's traffic.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VariantConfig {
+    pub qualifier: String,
+    /// Relative to the weights of the other variants. `0` sends the variant no traffic.
+    pub weight: u32,
+}
+
+/// Picks the variant of a client by hashing a request header or cookie. Set one of the two.
+/// Requests without it are spread by weight.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StickyConfig {
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default)]
+    pub cookie: Option<String>,
+}
+
+/// A function that receives copies of a route's request events without affecting its
+/// responses. Its results are only logged.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MirrorConfig {
+    pub lambda_function_name: String,
+    #[serde(default)]
+    pub qualifier: Option<String>,
+    #[serde(default)]
+    pub invocation_type: MirrorInvocationType,
+    /// Share of the route's requests that are mirrored, from 0 to 100.
+    #[serde(default = "default_mirror_percentage")]
+    pub percentage: u8,
+    /// Mirrored requests in flight at once. Requests sampled while this many are in flight are not
+    /// mirrored.
+    #[serde(default = "default_mirror_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+/// What a route serves when its function fails with one of `errors`: a fallback function invoked
+/// with the same event, or a static response. Set one of the two.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FallbackConfig {
+    #[serde(default)]
+    pub lambda_function_name: Option<String>,
+    #[serde(default)]
+    pub qualifier: Option<String>,
+    #[serde(default)]
+    pub response: Option<StaticResponseConfig>,
+    /// Gateway error classes, as returned by `GatewayError::class`, that trigger the fallback. A
+    /// function error matches `FunctionError` or `Timeout`, whatever the function's own `errorType`.
+    #[serde(default = "default_fallback_errors")]
+    pub errors: Vec<ErrorClass>,
+}
+
+/// A fixed HTTP response.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StaticResponseConfig {
+    #[serde(with = "http_serde::status_code")]
+    pub status_code: StatusCode,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default = "default_error_content_type")]
+    pub content_type: String,
+}
+
+/// A role assumed through STS with the client's default credentials.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct AssumeRoleConfig {
+    pub role_arn: String,
+    /// Required by the role's trust policy for some cross-account roles.
+    #[serde(default)]
+    pub external_id: Option<String>,
+    /// Shows up in the role's CloudTrail events. The SDK generates one when not set.
+    #[serde(default)]
+    pub session_name: Option<String>,
+    /// STS endpoint, e.g. a local stand-in.
+    #[serde(default)]
+    pub sts_endpoint_url: Option<String>,
+}
+
+impl LambdaClientConfig {
+    /// These settings, with unset fields taken from `defaults`.
+    pub fn or(&self, defaults: &LambdaClientConfig) -> LambdaClientConfig {
+        LambdaClientConfig {
+            region: self.region.clone().or_else(|| defaults.region.clone()),
+            profile: self.profile.clone().or_else(|| defaults.profile.clone()),
+            endpoint_url: self.endpoint_url.clone().or_else(|| defaults.endpoint_url.clone()),
+            connect_timeout: self.connect_timeout.or(defaults.connect_timeout),
+            read_timeout: self.read_timeout.or(defaults.read_timeout),
+            retry_mode: self.retry_mode.clone().or_else(|| defaults.retry_mode.clone()),
+            max_attempts: self.max_attempts.or(defaults.max_attempts),
+            pool_max_idle_per_host: self.pool_max_idle_per_host.or(defaults.pool_max_idle_per_host),
+            assume_role: self.assume_role.clone().or_else(|| defaults.assume_role.clone()),
+        }
+    }
+}
+
+/// A handler executable hosted by the gateway's Lambda Runtime API emulator.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LocalRuntimeConfig {
+    /// Path of the executable, e.g. a `bootstrap` binary built for a custom runtime.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables set for the process, in addition to the Lambda ones.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Number of processes, and so of concurrent invocations.
+    #[serde(default = "default_local_concurrency")]
+    pub concurrency: usize,
+    /// Seconds an invocation may run before the process is killed and restarted.
+    #[serde(default = "default_local_timeout")]
+    pub timeout: u64,
+    /// Reported to the function as `AWS_LAMBDA_FUNCTION_MEMORY_SIZE`. It is not enforced.
+    #[serde(default = "default_local_memory_size")]
+    pub memory_size: u64,
+}
+
+/// Routing and authentication for requests whose `Host` matches one of `hosts`.
+/// Unset fields fall back to the top-level settings.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VirtualHostConfig {
+    /// Exact host names or wildcards such as `*.example.com`.
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub lambda_function_name: String,
+    #[serde(default)]
+    pub lambda_invoke_mode: Option<LambdaInvokeMode>,
+    #[serde(default)]
+    pub payload_format: Option<PayloadFormat>,
+    #[serde(default)]
+    pub multi_value_headers: Option<bool>,
+    #[serde(default)]
+    pub raw_content_type: Option<String>,
+    #[serde(default)]
+    pub prelude_detection: Option<PreludeDetection>,
+    #[serde(default)]
+    pub stream_idle_timeout: Option<u64>,
+    #[serde(default)]
+    pub stream_max_duration: Option<u64>,
+    #[serde(default)]
+    pub sse: Option<bool>,
+    #[serde(default)]
+    pub sse_keepalive_interval: Option<u64>,
+    #[serde(default)]
+    pub lambda_client: LambdaClientConfig,
+    #[serde(default)]
+    pub regions: Option<Vec<String>>,
+    #[serde(default)]
+    pub request_template: Option<String>,
+    #[serde(default)]
+    pub response_template: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    #[serde(default)]
+    pub auth_mode: Option<AuthMode>,
+    #[serde(default)]
+    pub api_keys: Option<HashSet<String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RouteConfig {
+    /// Path pattern, e.g. `/users/{id}` or `/static/{proxy+}`.
+    pub path: String,
+    /// Allowed methods. Empty or `ANY` accepts every method.
+    #[serde(default)]
+    pub methods: Vec<String>,
+    pub lambda_function_name: String,
+    #[serde(default)]
+    pub qualifier: Option<String>,
+    /// Qualifiers sharing the route's traffic by weight, instead of `qualifier`.
+    #[serde(default)]
+    pub variants: Vec<VariantConfig>,
+    /// Keeps clients on one of the `variants`.
+    #[serde(default)]
+    pub sticky: Option<StickyConfig>,
+    /// Function sent a copy of the route's request events.
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    /// Served instead of an error response when the invocation fails.
+    #[serde(default)]
+    pub fallback: Option<FallbackConfig>,
+    /// Falls back to the global `lambda_invoke_mode` when not set.
+    #[serde(default)]
+    pub lambda_invoke_mode: Option<LambdaInvokeMode>,
+    /// Falls back to the global `payload_format` when not set.
+    #[serde(default)]
+    pub payload_format: Option<PayloadFormat>,
+    /// Falls back to the global `multi_value_headers` when not set.
+    #[serde(default)]
+    pub multi_value_headers: Option<bool>,
+    /// Falls back to the global `raw_content_type` when not set.
+    #[serde(default)]
+    pub raw_content_type: Option<String>,
+    /// Falls back to the global `prelude_detection` when not set.
+    #[serde(default)]
+    pub prelude_detection: Option<PreludeDetection>,
+    /// Falls back to the global `stream_idle_timeout` when not set.
+    #[serde(default)]
+    pub stream_idle_timeout: Option<u64>,
+    /// Falls back to the global `stream_max_duration` when not set.
+    #[serde(default)]
+    pub stream_max_duration: Option<u64>,
+    /// Falls back to the global `sse` when not set.
+    #[serde(default)]
+    pub sse: Option<bool>,
+    /// Falls back to the global `sse_keepalive_interval` when not set.
+    #[serde(default)]
+    pub sse_keepalive_interval: Option<u64>,
+    /// Fields not set fall back to the virtual host's and then the global `lambda_client` settings.
+    #[serde(default)]
+    pub lambda_client: LambdaClientConfig,
+    /// Falls back to the global `regions` when not set.
+    #[serde(default)]
+    pub regions: Option<Vec<String>>,
+    /// Setting either template replaces the inherited one; the other is still inherited.
+    #[serde(default)]
+    pub request_template: Option<String>,
+    #[serde(default)]
+    pub response_template: Option<String>,
 }
 
 impl Default for Config {
@@ -25,6 +380,34 @@ impl Default for Config {
             api_keys: HashSet::new(),
             auth_mode: default_auth_mode(),
             addr: default_addr(),
+            metrics_addr: None,
+            routes: Vec::new(),
+            virtual_hosts: Vec::new(),
+            payload_format: PayloadFormat::default(),
+            multi_value_headers: false,
+            raw_content_type: default_raw_content_type(),
+            binary_media_types: Vec::new(),
+            text_media_types: default_text_media_types(),
+            request_template: None,
+            response_template: None,
+            stage: default_stage(),
+            stage_variables: HashMap::new(),
+            error_mappings: Vec::new(),
+            show_stack_traces: false,
+            prelude_detection: PreludeDetection::default(),
+            max_prelude_size: default_max_prelude_size(),
+            stream_buffer_size: default_stream_buffer_size(),
+            stream_idle_timeout: None,
+            stream_max_duration: None,
+            sse: false,
+            sse_keepalive_interval: default_sse_keepalive_interval(),
+            stream_error_trailers: false,
+            lambda_client: LambdaClientConfig::default(),
+            regions: Vec::new(),
+            failover_ejection_threshold: default_failover_ejection_threshold(),
+            failover_ejection_duration: default_failover_ejection_duration(),
+            backend: Backend::default(),
+            local_runtime: None,
         }
     }
 }
@@ -43,14 +426,100 @@ impl Config {
         if let Ok(val) = std::env::var("LAMBDA_FUNCTION_NAME") {
             self.lambda_function_name = val;
         }
-        if self.lambda_function_name.is_empty() {
-            panic!("No lambda_function_name provided. Please set it in the config file or LAMBDA_FUNCTION_NAME environment variable.");
+        if self.lambda_function_name.is_empty() && self.routes.is_empty() && self.virtual_hosts.is_empty() {
+            panic!("No lambda_function_name provided. Please set it in the config file or LAMBDA_FUNCTION_NAME environment variable, or configure routes or virtual_hosts.");
         }
         if let Ok(val) = std::env::var("LAMBDA_INVOKE_MODE") {
             if let Ok(mode) = val.parse() {
                 self.lambda_invoke_mode = mode;
             }
         }
+        if let Ok(val) = std::env::var("PAYLOAD_FORMAT") {
+            if let Ok(format) = val.parse() {
+                self.payload_format = format;
+            }
+        }
+        if let Ok(val) = std::env::var("MULTI_VALUE_HEADERS") {
+            if let Ok(enabled) = val.parse() {
+                self.multi_value_headers = enabled;
+            }
+        }
+        if let Ok(val) = std::env::var("BINARY_MEDIA_TYPES") {
+            self.binary_media_types = val.split(',').filter(|s| !s.is_empty()).map(String::from).collect();
+        }
+        if let Ok(val) = std::env::var("TEXT_MEDIA_TYPES") {
+            self.text_media_types = val.split(',').filter(|s| !s.is_empty()).map(String::from).collect();
+        }
+        if let Ok(val) = std::env::var("SHOW_STACK_TRACES") {
+            if let Ok(enabled) = val.parse() {
+                self.show_stack_traces = enabled;
+            }
+        }
+        if let Ok(val) = std::env::var("PRELUDE_DETECTION") {
+            if let Ok(detection) = val.parse() {
+                self.prelude_detection = detection;
+            }
+        }
+        if let Ok(val) = std::env::var("MAX_PRELUDE_SIZE") {
+            if let Ok(size) = val.parse() {
+                self.max_prelude_size = size;
+            }
+        }
+        if let Ok(val) = std::env::var("STREAM_BUFFER_SIZE") {
+            if let Ok(size) = val.parse() {
+                self.stream_buffer_size = size;
+            }
+        }
+        if let Ok(val) = std::env::var("STREAM_IDLE_TIMEOUT") {
+            if let Ok(seconds) = val.parse() {
+                self.stream_idle_timeout = Some(seconds);
+            }
+        }
+        if let Ok(val) = std::env::var("STREAM_MAX_DURATION") {
+            if let Ok(seconds) = val.parse() {
+                self.stream_max_duration = Some(seconds);
+            }
+        }
+        if let Ok(val) = std::env::var("SSE") {
+            if let Ok(enabled) = val.parse() {
+                self.sse = enabled;
+            }
+        }
+        if let Ok(val) = std::env::var("SSE_KEEPALIVE_INTERVAL") {
+            if let Ok(seconds) = val.parse() {
+                self.sse_keepalive_interval = seconds;
+            }
+        }
+        if let Ok(val) = std::env::var("STREAM_ERROR_TRAILERS") {
+            if let Ok(enabled) = val.parse() {
+                self.stream_error_trailers = enabled;
+            }
+        }
+        if let Ok(val) = std::env::var("BACKEND") {
+            if let Ok(backend) = val.parse() {
+                self.backend = backend;
+            }
+        }
+        if let Ok(val) = std::env::var("LOCAL_RUNTIME_COMMAND") {
+            match &mut self.local_runtime {
+                Some(local_runtime) => local_runtime.command = val,
+                None => {
+                    self.local_runtime = Some(LocalRuntimeConfig {
+                        command: val,
+                        args: Vec::new(),
+                        environment: HashMap::new(),
+                        concurrency: default_local_concurrency(),
+                        timeout: default_local_timeout(),
+                        memory_size: default_local_memory_size(),
+                    })
+                }
+            }
+        }
+        if let Ok(val) = std::env::var("LOCAL_RUNTIME_CONCURRENCY") {
+            if let (Ok(concurrency), Some(local_runtime)) = (val.parse(), &mut self.local_runtime) {
+                local_runtime.concurrency = concurrency;
+            }
+        }
         if let Ok(val) = std::env::var("API_KEYS") {
             self.api_keys = val.split(',').filter(|s| !s.is_empty()).map(String::from).collect();
         }
@@ -62,6 +531,9 @@ impl Config {
         if let Ok(val) = std::env::var("ADDR") {
             self.addr = val;
         }
+        if let Ok(val) = std::env::var("METRICS_ADDR") {
+            self.metrics_addr = Some(val);
+        }
     }
 
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
@@ -88,28 +560,164 @@ fn default_addr() -> String {
     "0.0.0.0:8000".to_string()
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+fn default_raw_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_text_media_types() -> Vec<String> {
+    [
+        "text/*",
+        "application/json",
+        "application/*+json",
+        "application/xml",
+        "application/*+xml",
+        "application/javascript",
+        "application/x-www-form-urlencoded",
+        "application/graphql",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_max_prelude_size() -> usize {
+    64 * 1024
+}
+
+fn default_stream_buffer_size() -> usize {
+    64 * 1024
+}
+
+fn default_sse_keepalive_interval() -> u64 {
+    15
+}
+
+fn default_failover_ejection_threshold() -> u32 {
+    3
+}
+
+fn default_failover_ejection_duration() -> u64 {
+    30
+}
+
+fn default_fallback_errors() -> Vec<ErrorClass> {
+    vec![ErrorClass::FunctionError, ErrorClass::Timeout, ErrorClass::Throttled]
+}
+
+fn default_mirror_percentage() -> u8 {
+    100
+}
+
+fn default_mirror_max_in_flight() -> usize {
+    64
+}
+
+fn default_local_concurrency() -> usize {
+    1
+}
+
+fn default_local_timeout() -> u64 {
+    30
+}
+
+fn default_local_memory_size() -> u64 {
+    128
+}
+
+fn default_error_content_type() -> String {
+    "application/json".to_string()
+}
+
+fn default_stage() -> String {
+    "$default".to_string()
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AuthMode {
+    #[default]
     Open,
     ApiKey,
 }
 
-impl Default for AuthMode {
-    fn default() -> Self {
-        AuthMode::Open
-    }
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum LambdaInvokeMode {
+    #[default]
     Buffered,
     ResponseStream,
 }
 
-impl Default for LambdaInvokeMode {
-    fn default() -> Self {
-        LambdaInvokeMode::Buffered
-    }
+/// Shape of the event sent to the Lambda function.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// Application Load Balancer target event.
+    #[default]
+    Alb,
+    /// API Gateway REST API proxy integration event (payload format 1.0).
+    ApiGatewayV1,
+    /// API Gateway HTTP API and Lambda Function URL event (payload format 2.0).
+    ApiGatewayV2,
+    /// The request body is the event and the function result is the response body.
+    Raw,
+    /// The event is rendered by `request_template`; the result is mapped by `response_template`.
+    Template,
+}
+
+/// How the AWS SDK retries failed invocations.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RetryMode {
+    /// Retries with exponential backoff.
+    Standard,
+    /// Like `Standard`, and also slows down requests while Lambda throttles them.
+    Adaptive,
+}
+
+/// A class of invocation failures, named like the `errorType` of gateway error responses.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ErrorClass {
+    Throttled,
+    /// The function returned an error other than a timeout.
+    FunctionError,
+    /// The invocation or the function timed out.
+    Timeout,
+    InvalidResponse,
+    Unavailable,
+    InvokeFailed,
+}
+
+/// How mirrored requests are invoked.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MirrorInvocationType {
+    /// Queued by Lambda; only whether Lambda accepted the event is logged.
+    #[default]
+    Event,
+    /// Invoked like the route's function, and the response's status logged.
+    RequestResponse,
+}
+
+/// Where functions are invoked.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Backend {
+    /// AWS Lambda, through the AWS SDK.
+    #[default]
+    Aws,
+    /// Processes of the `local_runtime` executable, served by the gateway's Lambda Runtime API
+    /// emulator. Every function name is dispatched to them.
+    Local,
+}
+
+/// Whether a streamed response starts with a metadata prelude carrying its status, headers and
+/// cookies, terminated by eight NUL bytes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PreludeDetection {
+    /// Expect a prelude when the function streams with the
+    /// `application/vnd.awslambda.http-integration-response` content type, as the runtime's
+    /// HTTP response stream helpers do.
+    #[default]
+    ContentType,
+    /// Every response starts with a prelude.
+    Always,
+    /// Responses are streamed as-is.
+    Never,
 }
 
 impl FromStr for AuthMode {
@@ -135,3 +743,43 @@ impl FromStr for LambdaInvokeMode {
         }
     }
 }
+
+impl FromStr for PayloadFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "alb" => Ok(PayloadFormat::Alb),
+            "apigatewayv1" => Ok(PayloadFormat::ApiGatewayV1),
+            "apigatewayv2" => Ok(PayloadFormat::ApiGatewayV2),
+            "raw" => Ok(PayloadFormat::Raw),
+            "template" => Ok(PayloadFormat::Template),
+            _ => Err(format!("Invalid PayloadFormat: {}", s)),
+        }
+    }
+}
+
+impl FromStr for PreludeDetection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "contenttype" => Ok(PreludeDetection::ContentType),
+            "always" => Ok(PreludeDetection::Always),
+            "never" => Ok(PreludeDetection::Never),
+            _ => Err(format!("Invalid PreludeDetection: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "aws" => Ok(Backend::Aws),
+            "local" => Ok(Backend::Local),
+            _ => Err(format!("Invalid Backend: {}", s)),
+        }
+    }
+}
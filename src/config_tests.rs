@@ -22,6 +22,14 @@ fn test_lambda_invoke_mode_from_str() {
     assert!("invalid".parse::<LambdaInvokeMode>().is_err());
 }
 
+#[test]
+fn test_prelude_detection_from_str() {
+    assert_eq!("contenttype".parse::<PreludeDetection>().unwrap(), PreludeDetection::ContentType);
+    assert_eq!("Always".parse::<PreludeDetection>().unwrap(), PreludeDetection::Always);
+    assert_eq!("NEVER".parse::<PreludeDetection>().unwrap(), PreludeDetection::Never);
+    assert!("invalid".parse::<PreludeDetection>().is_err());
+}
+
 #[test]
 fn test_config_default() {
     let config = Config::default();
@@ -30,6 +38,7 @@ fn test_config_default() {
     assert!(config.api_keys.is_empty());
     assert_eq!(config.auth_mode, AuthMode::Open);
     assert_eq!(config.addr, "0.0.0.0:8000");
+    assert_eq!(config.metrics_addr, None);
 }
 
 #[test]
@@ -74,6 +83,7 @@ api_keys:
   - key2
 auth_mode: ApiKey
 addr: 127.0.0.1:3000
+metrics_addr: 127.0.0.1:9090
 "#;
 
     let mut temp_file = NamedTempFile::new().unwrap();
@@ -86,6 +96,7 @@ addr: 127.0.0.1:3000
     assert_eq!(config.api_keys, vec!["key1", "key2"].into_iter().map(String::from).collect::<HashSet<String>>());
     assert_eq!(config.auth_mode, AuthMode::ApiKey);
     assert_eq!(config.addr, "127.0.0.1:3000");
+    assert_eq!(config.metrics_addr.as_deref(), Some("127.0.0.1:9090"));
 }
 
 #[test]
@@ -189,3 +200,255 @@ fn test_config_load_empty_api_keys() {
     env::remove_var("API_KEYS");
     env::remove_var("LAMBDA_FUNCTION_NAME"); // Add this line
 }
+
+#[test]
+fn test_config_load_routes() {
+    let config_content = r#"
+lambda_invoke_mode: ResponseStream
+routes:
+  - path: /users/{id}
+    methods: [GET, DELETE]
+    lambda_function_name: users-function
+    qualifier: live
+  - path: /files/{proxy+}
+    lambda_function_name: files-function
+    lambda_invoke_mode: Buffered
+"#;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.lambda_function_name, "");
+    assert_eq!(config.routes.len(), 2);
+    assert_eq!(config.routes[0].path, "/users/{id}");
+    assert_eq!(config.routes[0].methods, vec!["GET", "DELETE"]);
+    assert_eq!(config.routes[0].lambda_function_name, "users-function");
+    assert_eq!(config.routes[0].qualifier.as_deref(), Some("live"));
+    assert_eq!(config.routes[0].lambda_invoke_mode, None);
+    assert!(config.routes[1].methods.is_empty());
+    assert_eq!(config.routes[1].lambda_invoke_mode, Some(LambdaInvokeMode::Buffered));
+}
+
+#[test]
+fn test_config_load_virtual_hosts() {
+    let config_content = r#"
+lambda_function_name: default-function
+virtual_hosts:
+  - hosts: [api.a.com, "*.a.com"]
+    lambda_function_name: a-function
+    auth_mode: ApiKey
+    api_keys:
+      - a-key
+  - hosts: [api.b.com]
+    routes:
+      - path: /orders/{id}
+        lambda_function_name: b-orders
+"#;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.virtual_hosts.len(), 2);
+    assert_eq!(config.virtual_hosts[0].hosts, vec!["api.a.com", "*.a.com"]);
+    assert_eq!(config.virtual_hosts[0].auth_mode, Some(AuthMode::ApiKey));
+    assert_eq!(config.virtual_hosts[0].api_keys, Some(HashSet::from(["a-key".to_string()])));
+    assert_eq!(config.virtual_hosts[1].lambda_function_name, "");
+    assert_eq!(config.virtual_hosts[1].auth_mode, None);
+    assert_eq!(config.virtual_hosts[1].routes[0].lambda_function_name, "b-orders");
+}
+
+#[test]
+fn test_config_load_error_mappings() {
+    let config_content = r#"
+lambda_function_name: test-function
+show_stack_traces: true
+error_mappings:
+  - error_type: "Validation*"
+    status_code: 400
+  - error_type: NotFound
+    status_code: 404
+    body: "<h1>Not Found</h1>"
+    content_type: text/html
+"#;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert!(config.show_stack_traces);
+    assert_eq!(config.error_mappings.len(), 2);
+    assert_eq!(config.error_mappings[0].status_code, StatusCode::BAD_REQUEST);
+    assert_eq!(config.error_mappings[0].body, None);
+    assert_eq!(config.error_mappings[0].content_type, "application/json");
+    assert_eq!(config.error_mappings[1].body.as_deref(), Some("<h1>Not Found</h1>"));
+    assert_eq!(config.error_mappings[1].content_type, "text/html");
+}
+
+#[test]
+fn test_config_load_invalid_error_mapping_status() {
+    let config_content = r#"
+lambda_function_name: test-function
+error_mappings:
+  - error_type: Error
+    status_code: 1000
+"#;
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    assert!(Config::load_from_file(temp_file.path()).is_err());
+}
+
+#[test]
+fn test_backend_from_str() {
+    assert_eq!("aws".parse::<Backend>().unwrap(), Backend::Aws);
+    assert_eq!("Local".parse::<Backend>().unwrap(), Backend::Local);
+    assert!("invalid".parse::<Backend>().is_err());
+}
+
+#[test]
+fn test_config_load_local_runtime() {
+    let config_content = r#"
+lambda_function_name: test-function
+backend: Local
+local_runtime:
+  command: ./bootstrap
+  environment:
+    RUST_LOG: debug
+  concurrency: 4
+"#;
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.backend, Backend::Local);
+    let local_runtime = config.local_runtime.unwrap();
+    assert_eq!(local_runtime.command, "./bootstrap");
+    assert_eq!(local_runtime.environment.get("RUST_LOG").unwrap(), "debug");
+    assert_eq!(local_runtime.concurrency, 4);
+    assert_eq!(local_runtime.timeout, 30);
+}
+
+#[test]
+fn test_config_load_lambda_client() {
+    let config_content = r#"
+lambda_function_name: test-function
+lambda_client:
+  region: eu-west-1
+  endpoint_url: http://localhost:9001
+  connect_timeout: 2
+  retry_mode: Adaptive
+  max_attempts: 5
+routes:
+  - path: /reports
+    lambda_function_name: reports-function
+    lambda_client:
+      read_timeout: 120
+      pool_max_idle_per_host: 32
+"#;
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.lambda_client.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(config.lambda_client.endpoint_url.as_deref(), Some("http://localhost:9001"));
+    assert_eq!(config.lambda_client.connect_timeout, Some(2));
+    assert_eq!(config.lambda_client.retry_mode, Some(RetryMode::Adaptive));
+    assert_eq!(config.lambda_client.max_attempts, Some(5));
+    let route = config.routes[0].lambda_client.or(&config.lambda_client);
+    assert_eq!(route.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(route.read_timeout, Some(120));
+    assert_eq!(route.pool_max_idle_per_host, Some(32));
+}
+
+#[test]
+fn test_config_load_assume_role() {
+    let config_content = r#"
+lambda_function_name: test-function
+routes:
+  - path: /billing
+    lambda_function_name: billing-function
+    lambda_client:
+      assume_role:
+        role_arn: arn:aws:iam::123456789012:role/gateway-invoker
+        external_id: gateway
+"#;
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.lambda_client.assume_role, None);
+    let assume_role = config.routes[0].lambda_client.assume_role.clone().unwrap();
+    assert_eq!(assume_role.role_arn, "arn:aws:iam::123456789012:role/gateway-invoker");
+    assert_eq!(assume_role.external_id.as_deref(), Some("gateway"));
+    assert_eq!(assume_role.session_name, None);
+    assert_eq!(assume_role.sts_endpoint_url, None);
+}
+
+#[test]
+fn test_config_load_regions() {
+    let config_content = r#"
+lambda_function_name: test-function
+regions: [us-east-1, us-west-2]
+failover_ejection_threshold: 5
+routes:
+  - path: /orders
+    lambda_function_name: orders-function
+    regions: [eu-west-1]
+"#;
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    assert_eq!(config.regions, vec!["us-east-1", "us-west-2"]);
+    assert_eq!(config.failover_ejection_threshold, 5);
+    assert_eq!(config.failover_ejection_duration, 30);
+    assert_eq!(config.routes[0].regions, Some(vec!["eu-west-1".to_string()]));
+}
+
+#[test]
+fn test_config_load_fallback() {
+    let config_content = r#"
+lambda_function_name: test-function
+routes:
+  - path: /orders
+    lambda_function_name: orders-function
+    fallback:
+      lambda_function_name: orders-fallback
+  - path: /users
+    lambda_function_name: users-function
+    fallback:
+      errors: [Unavailable]
+      response:
+        status_code: 503
+        headers:
+          retry-after: "30"
+        body: '{"message": "busy"}'
+"#;
+    let mut temp_file = NamedTempFile::new().unwrap();
+    write!(temp_file, "{}", config_content).unwrap();
+
+    let config = Config::load_from_file(temp_file.path()).unwrap();
+
+    let fallback = config.routes[0].fallback.as_ref().unwrap();
+    assert_eq!(fallback.lambda_function_name.as_deref(), Some("orders-fallback"));
+    assert_eq!(
+        fallback.errors,
+        vec![ErrorClass::FunctionError, ErrorClass::Timeout, ErrorClass::Throttled]
+    );
+    let fallback = config.routes[1].fallback.as_ref().unwrap();
+    assert_eq!(fallback.errors, vec![ErrorClass::Unavailable]);
+    let response = fallback.response.as_ref().unwrap();
+    assert_eq!(response.status_code, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers["retry-after"], "30");
+    assert_eq!(response.content_type, "application/json");
+}
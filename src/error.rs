@@ -0,0 +1,213 @@
+use crate::config::{Config, ErrorClass};
+use crate::media;
+use aws_sdk_lambda::config::http::HttpResponse;
+use aws_sdk_lambda::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::response::Response;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+
+/// Lambda reports function timeouts as a function error of this type.
+pub(crate) const TIMEOUT_ERROR_TYPE: &str = "Sandbox.Timedout";
+
+/// Failures while invoking a function or translating its result into an HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GatewayError {
+    /// Lambda throttled the invocation.
+    Throttled(String),
+    /// The function returned an error.
+    FunctionError(FunctionError),
+    /// The invocation or the function timed out.
+    Timeout(String),
+    /// The function result could not be turned into an HTTP response.
+    InvalidResponse(String),
+    /// Lambda failed with a 5xx service error, or could not be reached.
+    Unavailable(String),
+    /// The invocation failed for any other reason, e.g. a missing function or a permission error.
+    InvokeFailed(String),
+    /// The gateway failed before invoking the function.
+    Internal(String),
+}
+
+impl GatewayError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            GatewayError::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+            GatewayError::FunctionError(e) if e.is_timeout() => StatusCode::GATEWAY_TIMEOUT,
+            GatewayError::FunctionError(_) => StatusCode::BAD_GATEWAY,
+            GatewayError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            GatewayError::InvalidResponse(_) => StatusCode::BAD_GATEWAY,
+            GatewayError::Unavailable(_) => StatusCode::BAD_GATEWAY,
+            GatewayError::InvokeFailed(_) => StatusCode::BAD_GATEWAY,
+            GatewayError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The error class reported in the response body.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GatewayError::Throttled(_) => "Throttled",
+            GatewayError::FunctionError(e) if e.is_timeout() => "Timeout",
+            GatewayError::FunctionError(_) => "FunctionError",
+            GatewayError::Timeout(_) => "Timeout",
+            GatewayError::InvalidResponse(_) => "InvalidResponse",
+            GatewayError::Unavailable(_) => "Unavailable",
+            GatewayError::InvokeFailed(_) => "InvokeFailed",
+            GatewayError::Internal(_) => "Internal",
+        }
+    }
+
+    /// The class of the error, for rules that match errors. Internal errors have none.
+    pub fn class(&self) -> Option<ErrorClass> {
+        match self {
+            GatewayError::Throttled(_) => Some(ErrorClass::Throttled),
+            GatewayError::FunctionError(e) if e.is_timeout() => Some(ErrorClass::Timeout),
+            GatewayError::FunctionError(_) => Some(ErrorClass::FunctionError),
+            GatewayError::Timeout(_) => Some(ErrorClass::Timeout),
+            GatewayError::InvalidResponse(_) => Some(ErrorClass::InvalidResponse),
+            GatewayError::Unavailable(_) => Some(ErrorClass::Unavailable),
+            GatewayError::InvokeFailed(_) => Some(ErrorClass::InvokeFailed),
+            GatewayError::Internal(_) => None,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            GatewayError::FunctionError(e) => &e.error_message,
+            GatewayError::Throttled(m)
+            | GatewayError::Timeout(m)
+            | GatewayError::InvalidResponse(m)
+            | GatewayError::Unavailable(m)
+            | GatewayError::InvokeFailed(m)
+            | GatewayError::Internal(m) => m,
+        }
+    }
+
+    /// Builds the error response. Details stay in the logs; clients get the error class and the
+    /// request id to correlate with them.
+    ///
+    /// Function errors use the first matching `error_mappings` entry. A mapped error also reports
+    /// the function's `errorType` and `errorMessage`; the stack trace is only sent with
+    /// `show_stack_traces`.
+    pub fn into_response(self, request_id: &str, config: &Config) -> Response {
+        let mut status_code = self.status_code();
+        let mut function_error = None;
+
+        if let GatewayError::FunctionError(error) = &self {
+            let mapping = config
+                .error_mappings
+                .iter()
+                .find(|m| media::wildcard_match(&m.error_type, &error.error_type));
+            if let Some(mapping) = mapping {
+                if let Some(body) = &mapping.body {
+                    return Response::builder()
+                        .status(mapping.status_code)
+                        .header("content-type", mapping.content_type.as_str())
+                        .header("x-request-id", request_id)
+                        .body(Body::from(body.clone()))
+                        .unwrap_or_else(|_| GatewayError::Internal(String::new()).into_response(request_id, config));
+                }
+                status_code = mapping.status_code;
+            }
+            if mapping.is_some() || config.show_stack_traces {
+                function_error = Some(json!({
+                    "errorType": error.error_type,
+                    "errorMessage": error.error_message,
+                    "stackTrace": config.show_stack_traces.then_some(&error.stack_trace),
+                }));
+            }
+        }
+
+        let mut body = json!({
+            "message": status_code.canonical_reason().unwrap_or_default(),
+            "errorType": self.kind(),
+            "requestId": request_id,
+        });
+        if let Some(function_error) = function_error {
+            body["functionError"] = function_error;
+        }
+
+        Response::builder()
+            .status(status_code)
+            .header("content-type", "application/json")
+            .header("x-request-id", request_id)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// Classifies an error returned by an `Invoke` or `InvokeWithResponseStream` call.
+    pub fn from_sdk_error<E>(err: SdkError<E, HttpResponse>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+    {
+        let message = DisplayErrorContext(&err).to_string();
+        match &err {
+            SdkError::TimeoutError(_) => GatewayError::Timeout(message),
+            SdkError::DispatchFailure(failure) if failure.is_timeout() => GatewayError::Timeout(message),
+            SdkError::ServiceError(service) if service.err().code() == Some("TooManyRequestsException") => {
+                GatewayError::Throttled(message)
+            }
+            SdkError::ServiceError(service) if service.raw().status().is_server_error() => {
+                GatewayError::Unavailable(message)
+            }
+            SdkError::DispatchFailure(failure) if failure.is_io() => GatewayError::Unavailable(message),
+            _ => GatewayError::InvokeFailed(message),
+        }
+    }
+}
+
+/// The error object Lambda returns when a function fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionError {
+    #[serde(default)]
+    pub error_type: String,
+    #[serde(default)]
+    pub error_message: String,
+    #[serde(default)]
+    pub stack_trace: Vec<String>,
+}
+
+impl FunctionError {
+    /// Parses the error object of a failed invocation. `error_code` is the `FunctionError` of a
+    /// buffered invocation or the `ErrorCode` of a streamed one, and stands in for a missing
+    /// `errorType`. Payloads that are not error objects become the message.
+    pub fn parse(error_code: &str, payload: &[u8]) -> Self {
+        let mut error = serde_json::from_slice::<FunctionError>(payload).unwrap_or_else(|_| FunctionError {
+            error_message: String::from_utf8_lossy(payload).into_owned(),
+            ..Default::default()
+        });
+        if error.error_type.is_empty() {
+            error.error_type = error_code.to_string();
+        }
+        error
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.error_type == TIMEOUT_ERROR_TYPE
+    }
+}
+
+impl fmt::Display for FunctionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.error_message)
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GatewayError::FunctionError(e) => write!(f, "{}: {}", self.kind(), e),
+            _ => write!(f, "{}: {}", self.kind(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for GatewayError {}
+
+#[cfg(test)]
+mod tests {
+    include!("error_tests.rs");
+}
@@ -0,0 +1,157 @@
+use super::*;
+use crate::config::ErrorMapping;
+use aws_sdk_lambda::operation::invoke::InvokeError;
+use aws_smithy_types::body::SdkBody;
+use aws_smithy_types::error::ErrorMetadata;
+
+fn function_error(error_type: &str) -> GatewayError {
+    GatewayError::FunctionError(FunctionError {
+        error_type: error_type.to_string(),
+        error_message: "boom".to_string(),
+        stack_trace: vec!["at handler (index.js:1:1)".to_string()],
+    })
+}
+
+async fn json_body(response: Response) -> serde_json::Value {
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[test]
+fn test_status_codes() {
+    assert_eq!(GatewayError::Throttled(String::new()).status_code(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(function_error("Error").status_code(), StatusCode::BAD_GATEWAY);
+    assert_eq!(function_error("Sandbox.Timedout").status_code(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(GatewayError::Timeout(String::new()).status_code(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(GatewayError::InvalidResponse(String::new()).status_code(), StatusCode::BAD_GATEWAY);
+    assert_eq!(GatewayError::Unavailable(String::new()).status_code(), StatusCode::BAD_GATEWAY);
+    assert_eq!(GatewayError::InvokeFailed(String::new()).status_code(), StatusCode::BAD_GATEWAY);
+    assert_eq!(GatewayError::Internal(String::new()).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn test_into_response() {
+    let response = GatewayError::Timeout("Task timed out".to_string()).into_response("abc-123", &Config::default());
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.headers().get("x-request-id").unwrap(), "abc-123");
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+    assert_eq!(
+        json_body(response).await,
+        json!({"message": "Gateway Timeout", "errorType": "Timeout", "requestId": "abc-123"})
+    );
+}
+
+#[tokio::test]
+async fn test_into_response_hides_function_error() {
+    let response = function_error("Error").into_response("abc-123", &Config::default());
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        json_body(response).await,
+        json!({"message": "Bad Gateway", "errorType": "FunctionError", "requestId": "abc-123"})
+    );
+}
+
+#[tokio::test]
+async fn test_into_response_show_stack_traces() {
+    let config = Config {
+        show_stack_traces: true,
+        ..Default::default()
+    };
+    let response = function_error("Error").into_response("abc-123", &config);
+
+    let body = json_body(response).await;
+    assert_eq!(
+        body["functionError"],
+        json!({"errorType": "Error", "errorMessage": "boom", "stackTrace": ["at handler (index.js:1:1)"]})
+    );
+}
+
+#[tokio::test]
+async fn test_into_response_error_mappings() {
+    let config = Config {
+        error_mappings: vec![
+            ErrorMapping {
+                error_type: "Validation*".to_string(),
+                status_code: StatusCode::BAD_REQUEST,
+                body: None,
+                content_type: "application/json".to_string(),
+            },
+            ErrorMapping {
+                error_type: "NotFound".to_string(),
+                status_code: StatusCode::NOT_FOUND,
+                body: Some("<h1>Not Found</h1>".to_string()),
+                content_type: "text/html".to_string(),
+            },
+        ],
+        ..Default::default()
+    };
+
+    let response = function_error("ValidationError").into_response("abc-123", &config);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        json_body(response).await,
+        json!({
+            "message": "Bad Request",
+            "errorType": "FunctionError",
+            "requestId": "abc-123",
+            "functionError": {"errorType": "ValidationError", "errorMessage": "boom", "stackTrace": null},
+        })
+    );
+
+    let response = function_error("NotFound").into_response("abc-123", &config);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "<h1>Not Found</h1>");
+
+    let response = function_error("NotFoundError").into_response("abc-123", &config);
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[test]
+fn test_function_error_parse() {
+    let error = FunctionError::parse(
+        "Unhandled",
+        br#"{"errorMessage": "boom", "errorType": "TypeError", "stackTrace": ["line 1"]}"#,
+    );
+    assert_eq!(error.error_type, "TypeError");
+    assert_eq!(error.error_message, "boom");
+    assert_eq!(error.stack_trace, vec!["line 1"]);
+
+    let error = FunctionError::parse("Runtime.ExitError", b"process exited");
+    assert_eq!(error.error_type, "Runtime.ExitError");
+    assert_eq!(error.error_message, "process exited");
+    assert!(error.stack_trace.is_empty());
+}
+
+#[test]
+fn test_display() {
+    let error = GatewayError::InvokeFailed("function not found".to_string());
+    assert_eq!(error.to_string(), "InvokeFailed: function not found");
+    assert_eq!(function_error("TypeError").to_string(), "FunctionError: TypeError: boom");
+}
+
+#[test]
+fn test_from_sdk_error() {
+    let service_error = |status: u16, code: &str| {
+        let error = InvokeError::generic(ErrorMetadata::builder().code(code).build());
+        let response = HttpResponse::new(status.try_into().unwrap(), SdkBody::empty());
+        GatewayError::from_sdk_error(SdkError::service_error(error, response))
+    };
+
+    assert!(matches!(service_error(429, "TooManyRequestsException"), GatewayError::Throttled(_)));
+    assert!(matches!(service_error(500, "ServiceException"), GatewayError::Unavailable(_)));
+    assert!(matches!(service_error(502, "EC2UnexpectedException"), GatewayError::Unavailable(_)));
+    assert!(matches!(service_error(404, "ResourceNotFoundException"), GatewayError::InvokeFailed(_)));
+}
+
+#[test]
+fn test_class() {
+    assert_eq!(GatewayError::Throttled(String::new()).class(), Some(ErrorClass::Throttled));
+    assert_eq!(function_error("Error").class(), Some(ErrorClass::FunctionError));
+    assert_eq!(function_error("Sandbox.Timedout").class(), Some(ErrorClass::Timeout));
+    assert_eq!(GatewayError::Unavailable(String::new()).class(), Some(ErrorClass::Unavailable));
+    assert_eq!(GatewayError::Internal(String::new()).class(), None);
+}
@@ -0,0 +1,566 @@
+use crate::config::{Config, PayloadFormat};
+use crate::media;
+use crate::router::LambdaTarget;
+use axum::http::header::COOKIE;
+use axum::http::{HeaderMap, HeaderName, Method, Version};
+use base64::display::Base64Display;
+use base64::Engine;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The parts of an incoming HTTP request that end up in the Lambda event.
+pub struct HttpRequest<'a> {
+    pub request_id: &'a str,
+    pub method: &'a Method,
+    pub version: Version,
+    /// Percent-decoded request path.
+    pub path: &'a str,
+    /// Request path as received.
+    pub raw_path: &'a str,
+    pub headers: &'a HeaderMap,
+    /// Query string as received, without the leading `?`.
+    pub raw_query: &'a str,
+    /// Decoded query string pairs, in request order.
+    pub query: &'a [(String, String)],
+    pub body: &'a [u8],
+    pub source_ip: Option<IpAddr>,
+    /// The API key the request was authenticated with.
+    pub api_key: Option<&'a str>,
+    /// The matched route pattern, if the request matched a configured route.
+    pub resource: Option<&'a str>,
+    pub path_parameters: &'a HashMap<String, String>,
+}
+
+/// Serializes the Lambda event for `request` in the payload format of `target`.
+pub fn build_event(target: &LambdaTarget, request: &HttpRequest, config: &Config) -> Result<Vec<u8>, String> {
+    let result = match target.payload_format {
+        PayloadFormat::Alb => serde_json::to_vec(&alb_event(request, target.multi_value_headers, config)),
+        PayloadFormat::ApiGatewayV1 => serde_json::to_vec(&api_gateway_v1_event(request, config)),
+        PayloadFormat::ApiGatewayV2 => serde_json::to_vec(&api_gateway_v2_event(request, config)),
+        PayloadFormat::Raw => return Ok(request.body.to_vec()),
+        PayloadFormat::Template => {
+            let templates = target
+                .templates
+                .as_ref()
+                .ok_or_else(|| "No request_template configured".to_string())?;
+            return Ok(templates
+                .render_request(template_context(request, config))?
+                .into_bytes());
+        }
+    };
+    result.map_err(|e| format!("Failed to serialize event: {}", e))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AlbEvent<'a> {
+    http_method: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<Headers<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    multi_value_headers: Option<MultiValueHeaders<'a>>,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    query_string_parameters: Option<Query<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    multi_value_query_string_parameters: Option<MultiValueQuery<'a>>,
+    is_base64_encoded: bool,
+    body: EventBody<'a>,
+    request_context: AlbRequestContext,
+}
+
+#[derive(Serialize)]
+struct AlbRequestContext {
+    elb: Elb,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Elb {
+    target_group_arn: &'static str,
+}
+
+fn alb_event<'a>(request: &'a HttpRequest, multi_value_headers: bool, config: &Config) -> AlbEvent<'a> {
+    let body = EventBody::new(request, config);
+    let query = Query(request.query);
+
+    AlbEvent {
+        http_method: request.method.as_str(),
+        headers: (!multi_value_headers).then_some(Headers(request.headers)),
+        multi_value_headers: multi_value_headers.then_some(MultiValueHeaders(request.headers)),
+        path: request.path,
+        query_string_parameters: (!multi_value_headers).then_some(query),
+        multi_value_query_string_parameters: multi_value_headers.then_some(MultiValueQuery(request.query)),
+        is_base64_encoded: body.is_base64_encoded,
+        body,
+        request_context: AlbRequestContext {
+            elb: Elb { target_group_arn: "" },
+        },
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV1Event<'a> {
+    resource: &'a str,
+    path: &'a str,
+    http_method: &'a str,
+    headers: Option<Headers<'a>>,
+    multi_value_headers: Option<MultiValueHeaders<'a>>,
+    query_string_parameters: Option<Query<'a>>,
+    multi_value_query_string_parameters: Option<MultiValueQuery<'a>>,
+    path_parameters: Option<Cow<'a, HashMap<String, String>>>,
+    stage_variables: Option<&'a HashMap<String, String>>,
+    request_context: ApiGatewayV1RequestContext<'a>,
+    body: Option<EventBody<'a>>,
+    is_base64_encoded: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV1RequestContext<'a> {
+    account_id: &'static str,
+    api_id: &'static str,
+    resource_id: &'static str,
+    resource_path: &'a str,
+    http_method: &'a str,
+    path: &'a str,
+    protocol: &'static str,
+    stage: &'a str,
+    domain_name: Option<&'a str>,
+    request_id: &'a str,
+    request_time_epoch: u128,
+    identity: ApiGatewayV1Identity<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV1Identity<'a> {
+    source_ip: Option<IpAddr>,
+    user_agent: Option<&'a str>,
+}
+
+fn api_gateway_v1_event<'a>(request: &'a HttpRequest, config: &'a Config) -> ApiGatewayV1Event<'a> {
+    let body = EventBody::new(request, config);
+
+    // Requests that fell through to the default function look like a `/{proxy+}` resource.
+    let (resource, path_parameters) = match request.resource {
+        Some(resource) => (resource, Cow::Borrowed(request.path_parameters)),
+        None if request.path == "/" => ("/", Cow::Owned(HashMap::new())),
+        None => (
+            "/{proxy+}",
+            Cow::Owned(HashMap::from([(
+                "proxy".to_string(),
+                request.path.trim_start_matches('/').to_string(),
+            )])),
+        ),
+    };
+
+    let has_headers = !request.headers.is_empty();
+    let has_query = !request.query.is_empty();
+
+    ApiGatewayV1Event {
+        resource,
+        path: request.path,
+        http_method: request.method.as_str(),
+        headers: has_headers.then_some(Headers(request.headers)),
+        multi_value_headers: has_headers.then_some(MultiValueHeaders(request.headers)),
+        query_string_parameters: has_query.then_some(Query(request.query)),
+        multi_value_query_string_parameters: has_query.then_some(MultiValueQuery(request.query)),
+        path_parameters: (!path_parameters.is_empty()).then_some(path_parameters),
+        stage_variables: non_empty(&config.stage_variables),
+        request_context: ApiGatewayV1RequestContext {
+            account_id: "",
+            api_id: "",
+            resource_id: "",
+            resource_path: resource,
+            http_method: request.method.as_str(),
+            path: request.path,
+            protocol: protocol(request.version),
+            stage: &config.stage,
+            domain_name: header(request.headers, "host"),
+            request_id: request.request_id,
+            request_time_epoch: epoch_millis(),
+            identity: ApiGatewayV1Identity {
+                source_ip: request.source_ip,
+                user_agent: header(request.headers, "user-agent"),
+            },
+        },
+        is_base64_encoded: body.is_base64_encoded,
+        body: (!request.body.is_empty()).then_some(body),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV2Event<'a> {
+    version: &'static str,
+    route_key: RouteKey<'a>,
+    raw_path: &'a str,
+    raw_query_string: &'a str,
+    cookies: Option<Cookies<'a>>,
+    headers: JoinedHeaders<'a>,
+    query_string_parameters: Option<JoinedQuery<'a>>,
+    path_parameters: Option<&'a HashMap<String, String>>,
+    stage_variables: Option<&'a HashMap<String, String>>,
+    request_context: ApiGatewayV2RequestContext<'a>,
+    body: Option<EventBody<'a>>,
+    is_base64_encoded: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV2RequestContext<'a> {
+    account_id: &'static str,
+    api_id: &'static str,
+    domain_name: &'a str,
+    domain_prefix: &'a str,
+    http: ApiGatewayV2Http<'a>,
+    request_id: &'a str,
+    route_key: RouteKey<'a>,
+    stage: &'a str,
+    time_epoch: u128,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiGatewayV2Http<'a> {
+    method: &'a str,
+    path: &'a str,
+    protocol: &'static str,
+    source_ip: Option<IpAddr>,
+    user_agent: Option<&'a str>,
+}
+
+fn api_gateway_v2_event<'a>(request: &'a HttpRequest, config: &'a Config) -> ApiGatewayV2Event<'a> {
+    let body = EventBody::new(request, config);
+    let route_key = RouteKey {
+        method: request.method,
+        resource: request.resource,
+    };
+    let domain_name = header(request.headers, "host").unwrap_or_default();
+    let cookies = Cookies(request.headers);
+
+    ApiGatewayV2Event {
+        version: "2.0",
+        route_key,
+        raw_path: request.raw_path,
+        raw_query_string: request.raw_query,
+        cookies: cookies.iter().next().is_some().then_some(cookies),
+        headers: JoinedHeaders(request.headers),
+        query_string_parameters: (!request.query.is_empty()).then_some(JoinedQuery(request.query)),
+        path_parameters: non_empty(request.path_parameters),
+        stage_variables: non_empty(&config.stage_variables),
+        request_context: ApiGatewayV2RequestContext {
+            account_id: "",
+            api_id: "",
+            domain_name,
+            domain_prefix: domain_name.split('.').next().unwrap_or_default(),
+            http: ApiGatewayV2Http {
+                method: request.method.as_str(),
+                path: request.path,
+                protocol: protocol(request.version),
+                source_ip: request.source_ip,
+                user_agent: header(request.headers, "user-agent"),
+            },
+            request_id: request.request_id,
+            route_key,
+            stage: &config.stage,
+            time_epoch: epoch_millis(),
+        },
+        is_base64_encoded: body.is_base64_encoded,
+        body: (!request.body.is_empty()).then_some(body),
+    }
+}
+
+/// The request body as text, or base64 encoded while it is serialized when it is binary.
+#[derive(Clone, Copy)]
+struct EventBody<'a> {
+    body: &'a [u8],
+    is_base64_encoded: bool,
+}
+
+impl<'a> EventBody<'a> {
+    fn new(request: &'a HttpRequest, config: &Config) -> Self {
+        EventBody {
+            body: request.body,
+            is_base64_encoded: media::is_binary(request.headers, request.body, config),
+        }
+    }
+}
+
+impl Serialize for EventBody<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_base64_encoded {
+            serializer.collect_str(&Base64Display::new(
+                self.body,
+                &base64::engine::general_purpose::STANDARD,
+            ))
+        } else {
+            serializer.serialize_str(&String::from_utf8_lossy(self.body))
+        }
+    }
+}
+
+/// Headers with one value per name. Repeated headers keep the last value.
+#[derive(Clone, Copy)]
+struct Headers<'a>(&'a HeaderMap);
+
+impl Serialize for Headers<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.keys().filter_map(|name| {
+            let value = self.0.get_all(name).iter().next_back()?;
+            Some((name.as_str(), String::from_utf8_lossy(value.as_bytes())))
+        }))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct MultiValueHeaders<'a>(&'a HeaderMap);
+
+impl Serialize for MultiValueHeaders<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.keys().map(|name| (name.as_str(), HeaderValues(self.0, name))))
+    }
+}
+
+struct HeaderValues<'a>(&'a HeaderMap, &'a HeaderName);
+
+impl Serialize for HeaderValues<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.0
+                .get_all(self.1)
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes())),
+        )
+    }
+}
+
+/// Headers other than `cookie`, with repeated headers joined by commas.
+struct JoinedHeaders<'a>(&'a HeaderMap);
+
+impl Serialize for JoinedHeaders<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.keys().filter(|name| *name != COOKIE).map(|name| {
+            let mut values = self
+                .0
+                .get_all(name)
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()));
+            let mut value = values.next().unwrap_or_default();
+            for next in values {
+                let joined = value.to_mut();
+                joined.push(',');
+                joined.push_str(&next);
+            }
+            (name.as_str(), value)
+        }))
+    }
+}
+
+/// The individual cookies of all `cookie` headers.
+#[derive(Clone, Copy)]
+struct Cookies<'a>(&'a HeaderMap);
+
+impl<'a> Cookies<'a> {
+    fn iter(&self) -> impl Iterator<Item = &'a str> {
+        self.0
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+    }
+}
+
+impl Serialize for Cookies<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Query parameters with one value per key. Repeated keys keep the last value.
+#[derive(Clone, Copy)]
+struct Query<'a>(&'a [(String, String)]);
+
+impl Serialize for Query<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            group_query(self.0)
+                .into_iter()
+                .filter_map(|(key, values)| Some((key, *values.last()?))),
+        )
+    }
+}
+
+/// Query parameters with all values of each key, keys in order of first appearance.
+struct MultiValueQuery<'a>(&'a [(String, String)]);
+
+impl Serialize for MultiValueQuery<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(group_query(self.0))
+    }
+}
+
+/// Query parameters with the values of repeated keys joined by commas.
+struct JoinedQuery<'a>(&'a [(String, String)]);
+
+impl Serialize for JoinedQuery<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(group_query(self.0).into_iter().map(|(key, values)| {
+            let value = match values.as_slice() {
+                [value] => Cow::Borrowed(*value),
+                _ => Cow::Owned(values.join(",")),
+            };
+            (key, value)
+        }))
+    }
+}
+
+/// The values of each distinct key of `pairs`, keys in order of first appearance. Grouped in one
+/// pass, as a query string can hold any number of parameters.
+fn group_query(pairs: &[(String, String)]) -> Vec<(&str, Vec<&str>)> {
+    let mut positions: HashMap<&str, usize> = HashMap::with_capacity(pairs.len());
+    let mut groups: Vec<(&str, Vec<&str>)> = Vec::with_capacity(pairs.len());
+    for (key, value) in pairs {
+        let position = *positions.entry(key).or_insert_with(|| {
+            groups.push((key, Vec::new()));
+            groups.len() - 1
+        });
+        groups[position].1.push(value);
+    }
+    groups
+}
+
+/// `METHOD /resource` for a matched route, `$default` otherwise.
+#[derive(Clone, Copy)]
+struct RouteKey<'a> {
+    method: &'a Method,
+    resource: Option<&'a str>,
+}
+
+impl Serialize for RouteKey<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.resource {
+            Some(resource) => serializer.collect_str(&format_args!("{} {}", self.method, resource)),
+            None => serializer.serialize_str("$default"),
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+fn protocol(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2.0",
+        Version::HTTP_3 => "HTTP/3.0",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// Variables available to `request_template`.
+fn template_context(request: &HttpRequest, config: &Config) -> Value {
+    let mut query: HashMap<&str, &str> = HashMap::new();
+    let mut multi_value_query: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (k, v) in request.query {
+        query.insert(k.as_str(), v.as_str());
+        multi_value_query.entry(k.as_str()).or_default().push(v.as_str());
+    }
+    let source_ip = request.source_ip.map(|ip| ip.to_string());
+
+    json!({
+        "request_id": request.request_id,
+        "method": request.method.as_str(),
+        "path": request.path,
+        "raw_path": request.raw_path,
+        "raw_query": request.raw_query,
+        "path_params": request.path_parameters,
+        "headers": to_string_map(request.headers),
+        "multi_value_headers": to_multi_value_map(request.headers),
+        "query": query,
+        "multi_value_query": multi_value_query,
+        "body": String::from_utf8_lossy(request.body),
+        "body_base64": base64::engine::general_purpose::STANDARD.encode(request.body),
+        "json": serde_json::from_slice::<Value>(request.body).ok(),
+        "source_ip": source_ip,
+        // Lets SSE functions resume a stream without the headers of the request.
+        "last_event_id": header(request.headers, "last-event-id"),
+        "identity": {
+            "api_key": request.api_key,
+            "source_ip": source_ip,
+        },
+        "stage": config.stage,
+        "stage_variables": config.stage_variables,
+    })
+}
+
+/// Variables available to `response_template`.
+pub fn response_template_context(status_code: i32, function_error: Option<&str>, payload: &[u8]) -> Value {
+    json!({
+        "status_code": status_code,
+        "function_error": function_error,
+        "body": String::from_utf8_lossy(payload),
+        "json": serde_json::from_slice::<Value>(payload).ok(),
+    })
+}
+
+/// Parses a raw query string into decoded key/value pairs, keeping repeated keys.
+pub fn parse_query(query: Option<&str>) -> Vec<(String, String)> {
+    query
+        .map(|q| url::form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+        .unwrap_or_default()
+}
+
+/// Flattens headers to one value per name. Repeated headers keep the last value.
+pub fn to_string_map(headers: &HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.as_str().to_owned(),
+                String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+pub fn to_multi_value_map(headers: &HeaderMap) -> HashMap<String, Vec<String>> {
+    headers
+        .keys()
+        .map(|k| {
+            let values = headers
+                .get_all(k)
+                .iter()
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+                .collect();
+            (k.as_str().to_owned(), values)
+        })
+        .collect()
+}
+
+/// API Gateway sends `null` rather than an empty object for absent maps.
+fn non_empty<K, V>(map: &HashMap<K, V>) -> Option<&HashMap<K, V>> {
+    (!map.is_empty()).then_some(map)
+}
+
+fn epoch_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    include!("event_tests.rs");
+}
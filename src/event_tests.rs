@@ -0,0 +1,268 @@
+use super::*;
+use crate::template::Templates;
+use axum::http::HeaderValue;
+use std::sync::Arc;
+
+fn target(payload_format: PayloadFormat) -> LambdaTarget {
+    LambdaTarget {
+        payload_format,
+        ..LambdaTarget::for_test("function")
+    }
+}
+
+fn request<'a>(
+    method: &'a Method,
+    path: &'a str,
+    headers: &'a HeaderMap,
+    query: &'a [(String, String)],
+    body: &'a [u8],
+    resource: Option<&'a str>,
+    path_parameters: &'a HashMap<String, String>,
+) -> HttpRequest<'a> {
+    HttpRequest {
+        request_id: "request-1",
+        method,
+        version: Version::HTTP_11,
+        path,
+        raw_path: path,
+        headers,
+        raw_query: "",
+        query,
+        body,
+        source_ip: Some("192.0.2.1".parse().unwrap()),
+        api_key: None,
+        resource,
+        path_parameters,
+    }
+}
+
+#[tokio::test]
+async fn test_to_string_map() {
+    let mut headers = HeaderMap::new();
+    headers.insert("Content-Type", "application/json".parse().unwrap());
+    headers.insert("X-Custom-Header", "test-value".parse().unwrap());
+
+    let result = to_string_map(&headers);
+
+    assert_eq!(result.len(), 2);
+    assert_eq!(result.get("content-type"), Some(&"application/json".to_string()));
+    assert_eq!(result.get("x-custom-header"), Some(&"test-value".to_string()));
+}
+
+#[test]
+fn test_parse_query() {
+    let query = parse_query(Some("id=1&id=2&name=a%20b"));
+    assert_eq!(
+        query,
+        vec![
+            ("id".to_string(), "1".to_string()),
+            ("id".to_string(), "2".to_string()),
+            ("name".to_string(), "a b".to_string()),
+        ]
+    );
+    assert!(parse_query(None).is_empty());
+}
+
+#[test]
+fn test_alb_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+    let query = parse_query(Some("a=1"));
+    let params = HashMap::new();
+    let req = request(&Method::POST, "/items", &headers, &query, b"{}", None, &params);
+
+    let event: Value = serde_json::from_slice(&build_event(&target(PayloadFormat::Alb), &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(event["httpMethod"], "POST");
+    assert_eq!(event["path"], "/items");
+    assert_eq!(event["headers"]["content-type"], "application/json");
+    assert_eq!(event["queryStringParameters"]["a"], "1");
+    assert_eq!(event["body"], "{}");
+    assert_eq!(event["isBase64Encoded"], false);
+    assert!(event["requestContext"]["elb"].is_object());
+}
+
+#[test]
+fn test_alb_multi_value_event() {
+    let mut headers = HeaderMap::new();
+    headers.append("x-forwarded-for", HeaderValue::from_static("192.0.2.1"));
+    headers.append("x-forwarded-for", HeaderValue::from_static("192.0.2.2"));
+    let query = parse_query(Some("id=1&id=2"));
+    let params = HashMap::new();
+    let req = request(&Method::GET, "/items", &headers, &query, b"", None, &params);
+    let mut target = target(PayloadFormat::Alb);
+    target.multi_value_headers = true;
+
+    let event: Value = serde_json::from_slice(&build_event(&target, &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(event["multiValueHeaders"]["x-forwarded-for"], json!(["192.0.2.1", "192.0.2.2"]));
+    assert_eq!(event["multiValueQueryStringParameters"]["id"], json!(["1", "2"]));
+    assert!(event.get("headers").is_none());
+    assert!(event.get("queryStringParameters").is_none());
+}
+
+#[test]
+fn test_api_gateway_v1_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("host", HeaderValue::from_static("api.example.com"));
+    headers.append("accept", HeaderValue::from_static("text/html"));
+    headers.append("accept", HeaderValue::from_static("application/json"));
+    let query = parse_query(Some("id=1&id=2"));
+    let params = HashMap::from([("id".to_string(), "42".to_string())]);
+    let req = request(&Method::GET, "/users/42", &headers, &query, b"", Some("/users/{id}"), &params);
+    let config = Config {
+        stage: "prod".to_string(),
+        stage_variables: HashMap::from([("env".to_string(), "production".to_string())]),
+        ..Config::default()
+    };
+
+    let event: Value = serde_json::from_slice(&build_event(&target(PayloadFormat::ApiGatewayV1), &req, &config).unwrap()).unwrap();
+
+    assert_eq!(event["resource"], "/users/{id}");
+    assert_eq!(event["path"], "/users/42");
+    assert_eq!(event["httpMethod"], "GET");
+    assert_eq!(event["headers"]["accept"], "application/json");
+    assert_eq!(event["multiValueHeaders"]["accept"], json!(["text/html", "application/json"]));
+    assert_eq!(event["queryStringParameters"]["id"], "2");
+    assert_eq!(event["multiValueQueryStringParameters"]["id"], json!(["1", "2"]));
+    assert_eq!(event["pathParameters"]["id"], "42");
+    assert_eq!(event["stageVariables"]["env"], "production");
+    assert_eq!(event["body"], Value::Null);
+    assert_eq!(event["isBase64Encoded"], true);
+
+    let context = &event["requestContext"];
+    assert_eq!(context["requestId"], "request-1");
+    assert_eq!(context["stage"], "prod");
+    assert_eq!(context["resourcePath"], "/users/{id}");
+    assert_eq!(context["httpMethod"], "GET");
+    assert_eq!(context["domainName"], "api.example.com");
+    assert_eq!(context["protocol"], "HTTP/1.1");
+    assert_eq!(context["identity"]["sourceIp"], "192.0.2.1");
+    assert!(context["requestTimeEpoch"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_api_gateway_v1_event_default_route() {
+    let headers = HeaderMap::new();
+    let params = HashMap::new();
+    let req = request(&Method::GET, "/a/b", &headers, &[], b"", None, &params);
+
+    let event: Value = serde_json::from_slice(&build_event(&target(PayloadFormat::ApiGatewayV1), &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(event["resource"], "/{proxy+}");
+    assert_eq!(event["pathParameters"]["proxy"], "a/b");
+    assert_eq!(event["queryStringParameters"], Value::Null);
+    assert_eq!(event["stageVariables"], Value::Null);
+    assert_eq!(event["requestContext"]["stage"], "$default");
+}
+
+#[test]
+fn test_api_gateway_v2_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("host", HeaderValue::from_static("api.example.com"));
+    headers.append("accept", HeaderValue::from_static("text/html"));
+    headers.append("accept", HeaderValue::from_static("application/json"));
+    headers.append("cookie", HeaderValue::from_static("a=1; b=2"));
+    headers.append("cookie", HeaderValue::from_static("c=3"));
+    headers.insert("content-type", HeaderValue::from_static("text/plain"));
+    let query = parse_query(Some("id=1&id=2&q=x"));
+    let params = HashMap::from([("id".to_string(), "42".to_string())]);
+    let mut req = request(&Method::POST, "/users/42", &headers, &query, b"hi", Some("/users/{id}"), &params);
+    req.raw_query = "id=1&id=2&q=x";
+
+    let event: Value = serde_json::from_slice(&build_event(&target(PayloadFormat::ApiGatewayV2), &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(event["version"], "2.0");
+    assert_eq!(event["routeKey"], "POST /users/{id}");
+    assert_eq!(event["rawPath"], "/users/42");
+    assert_eq!(event["rawQueryString"], "id=1&id=2&q=x");
+    assert_eq!(event["cookies"], json!(["a=1", "b=2", "c=3"]));
+    assert_eq!(event["headers"]["accept"], "text/html,application/json");
+    assert!(event["headers"].get("cookie").is_none());
+    assert_eq!(event["queryStringParameters"]["id"], "1,2");
+    assert_eq!(event["queryStringParameters"]["q"], "x");
+    assert_eq!(event["pathParameters"]["id"], "42");
+    assert_eq!(event["body"], "hi");
+    assert_eq!(event["isBase64Encoded"], false);
+
+    let context = &event["requestContext"];
+    assert_eq!(context["domainName"], "api.example.com");
+    assert_eq!(context["domainPrefix"], "api");
+    assert_eq!(context["http"]["method"], "POST");
+    assert_eq!(context["http"]["path"], "/users/42");
+    assert_eq!(context["http"]["sourceIp"], "192.0.2.1");
+    assert_eq!(context["requestId"], "request-1");
+    assert_eq!(context["stage"], "$default");
+}
+
+#[test]
+fn test_api_gateway_v2_event_default_route() {
+    let headers = HeaderMap::new();
+    let params = HashMap::new();
+    let req = request(&Method::GET, "/", &headers, &[], b"", None, &params);
+
+    let event: Value = serde_json::from_slice(&build_event(&target(PayloadFormat::ApiGatewayV2), &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(event["routeKey"], "$default");
+    assert_eq!(event["cookies"], Value::Null);
+    assert_eq!(event["pathParameters"], Value::Null);
+    assert_eq!(event["body"], Value::Null);
+}
+
+#[test]
+fn test_raw_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("application/json"));
+    let params = HashMap::new();
+    let req = request(&Method::POST, "/rpc", &headers, &[], br#"{"a":1}"#, None, &params);
+
+    let event = build_event(&target(PayloadFormat::Raw), &req, &Config::default()).unwrap();
+
+    assert_eq!(event, br#"{"a":1}"#);
+}
+
+#[test]
+fn test_group_query() {
+    let query = parse_query(Some("b=1&a=2&b=3&c=&a=4"));
+
+    assert_eq!(
+        group_query(&query),
+        vec![("b", vec!["1", "3"]), ("a", vec!["2", "4"]), ("c", vec![""])]
+    );
+}
+
+#[test]
+fn test_binary_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_static("application/octet-stream"));
+    let query = parse_query(Some("id=1&id=2"));
+    let params = HashMap::new();
+    let req = request(&Method::POST, "/items", &headers, &query, &[0, 159, 146, 150], None, &params);
+
+    let event = build_event(&target(PayloadFormat::Alb), &req, &Config::default()).unwrap();
+
+    let event: Value = serde_json::from_slice(&event).unwrap();
+    assert_eq!(event["body"], "AJ+Slg==");
+    assert_eq!(event["isBase64Encoded"], true);
+    assert_eq!(event["queryStringParameters"], json!({"id": "2"}));
+}
+
+#[test]
+fn test_template_event() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-tenant", HeaderValue::from_static("acme"));
+    let query = parse_query(Some("limit=10"));
+    let params = HashMap::from([("id".to_string(), "42".to_string())]);
+    let mut req = request(&Method::POST, "/orders/42", &headers, &query, br#"{"qty":2}"#, Some("/orders/{id}"), &params);
+    req.api_key = Some("key1");
+    let mut target = target(PayloadFormat::Template);
+    let template = r#"{"action": "{{ method|lower }}", "id": {{ path_params.id|tojson }}, "tenant": {{ headers["x-tenant"]|tojson }}, "limit": {{ query.limit|int }}, "qty": {{ json.qty }}, "key": {{ identity.api_key|tojson }}}"#;
+    target.templates = Some(Arc::new(Templates::new(Some(template), None).unwrap()));
+
+    let event: Value = serde_json::from_slice(&build_event(&target, &req, &Config::default()).unwrap()).unwrap();
+
+    assert_eq!(
+        event,
+        json!({"action": "post", "id": "42", "tenant": "acme", "limit": 10, "qty": 2, "key": "key1"})
+    );
+}
@@ -0,0 +1,187 @@
+use crate::config::Config;
+use crate::error::GatewayError;
+use crate::invoker::{InvokeResponse, Invoker, ResponseStream};
+use crate::router::LambdaTarget;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Invokes targets with `regions` in their first healthy region, and fails over to the next one
+/// when Lambda throttles the invocation or is unavailable. Targets without regions go straight
+/// to the inner invoker.
+///
+/// A region that fails `failover_ejection_threshold` times in a row for a function is skipped
+/// for `failover_ejection_duration`, unless all other regions fail too. After that it is tried
+/// again, and ejected again on its next failure.
+pub struct FailoverInvoker {
+    inner: Arc<dyn Invoker>,
+    ejection_threshold: u32,
+    ejection_duration: Duration,
+    /// Health by function name and region.
+    health: Mutex<HashMap<String, HashMap<String, RegionHealth>>>,
+}
+
+/// Failures of a function in a region since its last success.
+#[derive(Debug, Default)]
+struct RegionHealth {
+    failures: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl FailoverInvoker {
+    pub fn new(inner: Arc<dyn Invoker>, config: &Config) -> Self {
+        FailoverInvoker {
+            inner,
+            ejection_threshold: config.failover_ejection_threshold,
+            ejection_duration: Duration::from_secs(config.failover_ejection_duration),
+            health: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The regions of `target` in the order they are tried: those not ejected first, then the
+    /// ejected ones, each in priority order.
+    fn regions<'a>(&self, target: &'a LambdaTarget) -> Vec<&'a str> {
+        let now = Instant::now();
+        let health = self.health.lock().unwrap();
+        let regions = health.get(&target.function_name);
+        let (available, ejected): (Vec<&str>, Vec<&str>) =
+            target.regions.iter().map(String::as_str).partition(|region| {
+                regions
+                    .and_then(|regions| regions.get(*region))
+                    .and_then(|health| health.ejected_until)
+                    .is_none_or(|until| until <= now)
+            });
+        available.into_iter().chain(ejected).collect()
+    }
+
+    fn record_success(&self, target: &LambdaTarget, region: &str) {
+        let mut health = self.health.lock().unwrap();
+        if let Some(regions) = health.get_mut(&target.function_name) {
+            regions.remove(region);
+        }
+    }
+
+    fn record_failure(&self, target: &LambdaTarget, region: &str) {
+        let mut health = self.health.lock().unwrap();
+        let region_health = health
+            .entry(target.function_name.clone())
+            .or_default()
+            .entry(region.to_string())
+            .or_default();
+        region_health.failures += 1;
+        if region_health.failures >= self.ejection_threshold {
+            region_health.ejected_until = Some(Instant::now() + self.ejection_duration);
+            tracing::warn!(
+                function_name = %target.function_name,
+                region,
+                failures = region_health.failures,
+                "Ejecting region"
+            );
+        }
+    }
+
+    /// Runs `invoke` in each region of `target` until one does not fail over.
+    async fn failover<'a, T>(
+        &'a self,
+        target: &'a LambdaTarget,
+        mut payload: Vec<u8>,
+        invoke: impl for<'t> Fn(&'t dyn Invoker, &'t LambdaTarget, Vec<u8>) -> BoxFuture<'t, Result<T, GatewayError>>,
+    ) -> Result<T, GatewayError> {
+        if target.regions.is_empty() {
+            return invoke(self.inner.as_ref(), target, payload).await;
+        }
+        let regions = self.regions(target);
+        let mut error = None;
+        for (i, region) in regions.iter().enumerate() {
+            let regional = in_region(target, region);
+            let payload = if i + 1 < regions.len() {
+                payload.clone()
+            } else {
+                std::mem::take(&mut payload)
+            };
+            match invoke(self.inner.as_ref(), &regional, payload).await {
+                Err(e) if fails_over(&e) => {
+                    tracing::warn!(region, error = %e, "Invocation failed in region");
+                    self.record_failure(target, region);
+                    error = Some(e);
+                }
+                result => {
+                    self.record_success(target, region);
+                    return result;
+                }
+            }
+        }
+        Err(error.expect("targets with regions are tried at least once"))
+    }
+}
+
+impl Invoker for FailoverInvoker {
+    fn invoke<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<InvokeResponse, GatewayError>> {
+        self.failover(target, payload, |invoker, target, payload| {
+            invoker.invoke(target, payload)
+        })
+        .boxed()
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<ResponseStream, GatewayError>> {
+        self.failover(target, payload, |invoker, target, payload| {
+            invoker.invoke_stream(target, payload)
+        })
+        .boxed()
+    }
+
+    fn invoke_event<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), GatewayError>> {
+        self.failover(target, payload, |invoker, target, payload| {
+            invoker.invoke_event(target, payload)
+        })
+        .boxed()
+    }
+}
+
+/// Whether an invocation that failed with `error` is retried in the next region. Timeouts are
+/// not, as the function may have run.
+fn fails_over(error: &GatewayError) -> bool {
+    matches!(error, GatewayError::Throttled(_) | GatewayError::Unavailable(_))
+}
+
+/// The targets invoked for `target`: one per region, or `target` itself.
+pub fn targets(target: &LambdaTarget) -> Vec<LambdaTarget> {
+    if target.regions.is_empty() {
+        return vec![target.clone()];
+    }
+    target.regions.iter().map(|region| in_region(target, region)).collect()
+}
+
+/// `target` invoked in `region`. A function ARN is changed to the one in `region`.
+fn in_region(target: &LambdaTarget, region: &str) -> LambdaTarget {
+    let mut regional = target.clone();
+    regional.lambda_client.region = Some(region.to_string());
+    regional.regions = Vec::new();
+    if target.function_name.starts_with("arn:") {
+        let mut parts: Vec<&str> = target.function_name.split(':').collect();
+        if parts.len() > 3 {
+            parts[3] = region;
+            regional.function_name = parts.join(":");
+        }
+    }
+    regional
+}
+
+#[cfg(test)]
+mod tests {
+    include!("failover_tests.rs");
+}
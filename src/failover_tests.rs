@@ -0,0 +1,135 @@
+use super::*;
+use crate::config::{PayloadFormat};
+use crate::invoker::{MockInvoker, StreamEvent};
+use bytes::Bytes;
+use futures::StreamExt;
+
+const EAST: &str = "arn:aws:lambda:us-east-1:123456789012:function:orders";
+const WEST: &str = "arn:aws:lambda:us-west-2:123456789012:function:orders";
+const EU: &str = "arn:aws:lambda:eu-west-1:123456789012:function:orders";
+
+fn target() -> LambdaTarget {
+    LambdaTarget {
+        payload_format: PayloadFormat::ApiGatewayV2,
+        regions: vec!["us-east-1".to_string(), "us-west-2".to_string(), "eu-west-1".to_string()],
+        ..LambdaTarget::for_test(EAST)
+    }
+}
+
+fn failover(mock: MockInvoker, config: &Config) -> (Arc<MockInvoker>, FailoverInvoker) {
+    let mock = Arc::new(mock);
+    (mock.clone(), FailoverInvoker::new(mock, config))
+}
+
+fn invoked(mock: &MockInvoker) -> Vec<String> {
+    mock.invocations().into_iter().map(|i| i.function_name).collect()
+}
+
+#[test]
+fn test_in_region() {
+    let regional = in_region(&target(), "us-west-2");
+    assert_eq!(regional.function_name, WEST);
+    assert_eq!(regional.lambda_client.region.as_deref(), Some("us-west-2"));
+    assert!(regional.regions.is_empty());
+
+    let mut named = target();
+    named.function_name = "orders".to_string();
+    assert_eq!(in_region(&named, "us-west-2").function_name, "orders");
+    assert_eq!(targets(&named).len(), 3);
+}
+
+#[tokio::test]
+async fn test_failover_on_throttling() {
+    let mock = MockInvoker::new()
+        .with_error(EAST, GatewayError::Throttled("slow down".to_string()))
+        .with_error(WEST, GatewayError::Unavailable("connection refused".to_string()))
+        .with_response(EU, InvokeResponse::new("eu"));
+    let (mock, invoker) = failover(mock, &Config::default());
+
+    let response = invoker.invoke(&target(), b"event".to_vec()).await.unwrap();
+
+    assert_eq!(response.payload, "eu");
+    assert_eq!(invoked(&mock), vec![EAST, WEST, EU]);
+    assert!(mock.invocations().iter().all(|i| i.payload == b"event"));
+}
+
+#[tokio::test]
+async fn test_no_failover_on_other_errors() {
+    let mock = MockInvoker::new()
+        .with_error(EAST, GatewayError::Timeout("timed out".to_string()))
+        .with_response(WEST, InvokeResponse::new("west"));
+    let (mock, invoker) = failover(mock, &Config::default());
+
+    let error = invoker.invoke(&target(), Vec::new()).await.unwrap_err();
+
+    assert!(matches!(error, GatewayError::Timeout(_)));
+    assert_eq!(invoked(&mock), vec![EAST]);
+}
+
+#[tokio::test]
+async fn test_last_region_error() {
+    let mock = MockInvoker::new()
+        .with_error(EAST, GatewayError::Throttled("east".to_string()))
+        .with_error(WEST, GatewayError::Throttled("west".to_string()))
+        .with_error(EU, GatewayError::Throttled("eu".to_string()));
+    let (_, invoker) = failover(mock, &Config::default());
+
+    let error = invoker.invoke(&target(), Vec::new()).await.unwrap_err();
+
+    assert_eq!(error, GatewayError::Throttled("eu".to_string()));
+}
+
+#[tokio::test]
+async fn test_region_ejection() {
+    let mock = MockInvoker::new()
+        .with_error(EAST, GatewayError::Unavailable("down".to_string()))
+        .with_response(WEST, InvokeResponse::new("west"));
+    let config = Config {
+        failover_ejection_threshold: 2,
+        ..Config::default()
+    };
+    let (mock, invoker) = failover(mock, &config);
+
+    for _ in 0..3 {
+        invoker.invoke(&target(), Vec::new()).await.unwrap();
+    }
+
+    // The third request skips the ejected region.
+    assert_eq!(invoked(&mock), vec![EAST, WEST, EAST, WEST, WEST]);
+    assert_eq!(invoker.regions(&target()), vec!["us-west-2", "eu-west-1", "us-east-1"]);
+
+    let config = Config {
+        failover_ejection_threshold: 1,
+        failover_ejection_duration: 0,
+        ..Config::default()
+    };
+    let mock = MockInvoker::new().with_error(EAST, GatewayError::Unavailable("down".to_string()));
+    let (_, invoker) = failover(mock, &config);
+    invoker.invoke(&target(), Vec::new()).await.unwrap_err();
+    assert_eq!(invoker.regions(&target())[0], "us-east-1", "ejection expired");
+}
+
+#[tokio::test]
+async fn test_stream_failover() {
+    let mock = MockInvoker::new()
+        .with_error(EAST, GatewayError::Throttled("slow down".to_string()))
+        .with_stream(WEST, None, vec![Ok(StreamEvent::Data(Bytes::from_static(b"west")))]);
+    let (_, invoker) = failover(mock, &Config::default());
+
+    let stream = invoker.invoke_stream(&target(), Vec::new()).await.unwrap();
+
+    let events: Vec<_> = stream.events.collect().await;
+    assert_eq!(events, vec![Ok(StreamEvent::Data(Bytes::from_static(b"west")))]);
+}
+
+#[tokio::test]
+async fn test_without_regions() {
+    let mut target = target();
+    target.regions = Vec::new();
+    let mock = MockInvoker::new().with_error(EAST, GatewayError::Throttled("slow down".to_string()));
+    let (mock, invoker) = failover(mock, &Config::default());
+
+    invoker.invoke(&target, Vec::new()).await.unwrap_err();
+
+    assert_eq!(invoked(&mock), vec![EAST]);
+}
@@ -0,0 +1,107 @@
+use crate::config::{ErrorClass, RouteConfig};
+use crate::error::GatewayError;
+use crate::router::LambdaTarget;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::Response;
+
+/// Response header set on fallback responses, holding the error class of the failed invocation.
+pub const FALLBACK_HEADER: &str = "x-gateway-fallback";
+
+/// What a route serves when its function fails with one of `errors`.
+#[derive(Clone, Debug)]
+pub struct Fallback {
+    pub errors: Vec<ErrorClass>,
+    pub action: FallbackAction,
+}
+
+#[derive(Clone, Debug)]
+pub enum FallbackAction {
+    /// Invokes another function with the same event, with the settings of the route.
+    Function(Box<LambdaTarget>),
+    Response(StaticResponse),
+}
+
+#[derive(Clone, Debug)]
+pub struct StaticResponse {
+    pub status_code: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Fallback {
+    /// The fallback of `route`, whose function is invoked as `target`. `None` when the route has
+    /// no fallback.
+    pub(crate) fn from_config(route: &RouteConfig, target: &LambdaTarget) -> Result<Option<Self>, String> {
+        let Some(fallback) = &route.fallback else {
+            return Ok(None);
+        };
+        if fallback.errors.is_empty() {
+            return Err(format!("No fallback errors provided for route {}", route.path));
+        }
+        let action = match (&fallback.lambda_function_name, &fallback.response) {
+            (Some(function_name), None) if !function_name.is_empty() => {
+                FallbackAction::Function(Box::new(LambdaTarget {
+                    function_name: function_name.clone(),
+                    qualifier: fallback.qualifier.clone(),
+                    ..target.clone()
+                }))
+            }
+            (None, Some(response)) => {
+                let mut headers = HeaderMap::new();
+                let content_type = HeaderValue::from_str(&response.content_type)
+                    .map_err(|_| format!("Invalid fallback content_type for route {}", route.path))?;
+                headers.insert("content-type", content_type);
+                for (name, value) in &response.headers {
+                    let name = HeaderName::try_from(name.as_str())
+                        .map_err(|_| format!("Invalid fallback header for route {}: {}", route.path, name))?;
+                    let value = HeaderValue::from_str(value)
+                        .map_err(|_| format!("Invalid fallback header for route {}: {}", route.path, name))?;
+                    headers.insert(name, value);
+                }
+                FallbackAction::Response(StaticResponse {
+                    status_code: response.status_code,
+                    headers,
+                    body: Bytes::from(response.body.clone()),
+                })
+            }
+            _ => {
+                return Err(format!(
+                    "Set one of lambda_function_name or response in the fallback of route {}",
+                    route.path
+                ))
+            }
+        };
+        Ok(Some(Fallback {
+            errors: fallback.errors.clone(),
+            action,
+        }))
+    }
+
+    /// Whether the fallback is served for `error`.
+    pub fn handles(&self, error: &GatewayError) -> bool {
+        error.class().is_some_and(|class| self.errors.contains(&class))
+    }
+
+    /// The function invoked by the fallback, if any.
+    pub fn target(&self) -> Option<&LambdaTarget> {
+        match &self.action {
+            FallbackAction::Function(target) => Some(target),
+            FallbackAction::Response(_) => None,
+        }
+    }
+}
+
+impl StaticResponse {
+    pub fn to_response(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status_code;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("fallback_tests.rs");
+}
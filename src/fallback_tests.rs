@@ -0,0 +1,79 @@
+use super::*;
+use crate::config::{FallbackConfig, PayloadFormat};
+use crate::error::FunctionError;
+
+fn target() -> LambdaTarget {
+    LambdaTarget {
+        qualifier: Some("live".to_string()),
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..LambdaTarget::for_test("orders")
+    }
+}
+
+fn fallback(config: &str) -> Result<Option<Fallback>, String> {
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: orders}").unwrap();
+    route.fallback = Some(serde_yaml::from_str::<FallbackConfig>(config).unwrap());
+    Fallback::from_config(&route, &target())
+}
+
+fn function_error(error_type: &str) -> GatewayError {
+    GatewayError::FunctionError(FunctionError {
+        error_type: error_type.to_string(),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_function_fallback() {
+    let fallback = fallback("{lambda_function_name: orders-fallback}").unwrap().unwrap();
+
+    let target = fallback.target().unwrap();
+    assert_eq!(target.function_name, "orders-fallback");
+    assert_eq!(target.qualifier, None);
+    assert_eq!(target.payload_format, PayloadFormat::ApiGatewayV2);
+    assert!(fallback.handles(&function_error("Error")));
+    assert!(fallback.handles(&function_error("Sandbox.Timedout")));
+    assert!(fallback.handles(&GatewayError::Throttled(String::new())));
+    assert!(!fallback.handles(&GatewayError::InvokeFailed(String::new())));
+    assert!(!fallback.handles(&GatewayError::Internal(String::new())));
+}
+
+#[tokio::test]
+async fn test_static_fallback() {
+    let fallback = fallback(
+        r#"{response: {status_code: 503, headers: {retry-after: "30"}, body: "{}"}, errors: [Unavailable]}"#,
+    )
+    .unwrap()
+    .unwrap();
+
+    assert!(fallback.target().is_none());
+    assert!(fallback.handles(&GatewayError::Unavailable(String::new())));
+    assert!(!fallback.handles(&function_error("Error")));
+    let FallbackAction::Response(response) = &fallback.action else {
+        panic!("expected a static response");
+    };
+    let response = response.to_response();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.headers()["retry-after"], "30");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "{}");
+}
+
+#[test]
+fn test_invalid_fallback() {
+    let one_of = "Set one of lambda_function_name or response in the fallback of route /orders";
+    assert_eq!(fallback("{}").unwrap_err(), one_of);
+    assert_eq!(
+        fallback("{lambda_function_name: a, response: {status_code: 503}}").unwrap_err(),
+        one_of
+    );
+    assert_eq!(
+        fallback("{lambda_function_name: a, errors: []}").unwrap_err(),
+        "No fallback errors provided for route /orders"
+    );
+    assert_eq!(
+        fallback(r#"{response: {status_code: 503, headers: {"bad header": "x"}}}"#).unwrap_err(),
+        "Invalid fallback header for route /orders: bad header"
+    );
+}
@@ -0,0 +1,458 @@
+use crate::config::{AssumeRoleConfig, LambdaClientConfig, RetryMode};
+use crate::error::{FunctionError, GatewayError};
+use crate::router::LambdaTarget;
+use aws_config::identity::IdentityCache;
+use aws_config::retry::RetryConfig;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::timeout::TimeoutConfig;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_sdk_lambda::error::DisplayErrorContext;
+use aws_sdk_lambda::types::InvokeWithResponseStreamResponseEvent::{InvokeComplete, PayloadChunk};
+use aws_sdk_lambda::types::{InvocationType, InvokeWithResponseStreamCompleteEvent, ResponseStreamingInvocationType};
+use aws_sdk_lambda::Client;
+use aws_smithy_runtime::client::http::hyper_014::HyperClientBuilder;
+use aws_smithy_types::Blob;
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{FutureExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Invokes the functions behind routes. The gateway talks to Lambda only through this trait, so
+/// backends can be swapped and the handler can be tested without AWS.
+pub trait Invoker: Send + Sync {
+    /// Invokes `target` and waits for its whole response.
+    fn invoke<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<InvokeResponse, GatewayError>>;
+
+    /// Invokes `target` and returns once the response stream has started.
+    fn invoke_stream<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<ResponseStream, GatewayError>>;
+
+    /// Queues an asynchronous invocation of `target`, like the `Event` invocation type, and
+    /// returns once it is accepted. The function's result is not reported.
+    fn invoke_event<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), GatewayError>>;
+}
+
+/// The result of a buffered invocation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InvokeResponse {
+    /// The status code of the invocation, not of the HTTP response.
+    pub status_code: i32,
+    /// Set when the function failed, e.g. `Unhandled`.
+    pub function_error: Option<String>,
+    pub payload: Bytes,
+}
+
+impl InvokeResponse {
+    /// A successful invocation returning `payload`.
+    pub fn new(payload: impl Into<Bytes>) -> Self {
+        InvokeResponse {
+            status_code: 200,
+            function_error: None,
+            payload: payload.into(),
+        }
+    }
+
+    /// An invocation where the function failed with `payload` as the error.
+    pub fn function_error(function_error: &str, payload: impl Into<Bytes>) -> Self {
+        InvokeResponse {
+            function_error: Some(function_error.to_string()),
+            ..InvokeResponse::new(payload)
+        }
+    }
+}
+
+/// The events of a streamed response. Dropping the stream cancels the invocation.
+pub struct ResponseStream {
+    /// The content type the function set for the stream.
+    pub content_type: Option<String>,
+    pub events: BoxStream<'static, Result<StreamEvent, GatewayError>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The next part of the response.
+    Data(Bytes),
+    /// The end of the stream, with the error if the function failed while streaming.
+    Complete(Option<FunctionError>),
+}
+
+/// Invokes functions with the AWS SDK, using one client for each distinct `lambda_client`
+/// setting of the targets.
+#[derive(Clone, Debug)]
+pub struct AwsInvoker {
+    clients: HashMap<LambdaClientConfig, Client>,
+}
+
+impl AwsInvoker {
+    /// Builds the clients for `targets`.
+    pub async fn new<'a>(targets: impl IntoIterator<Item = &'a LambdaTarget>) -> Self {
+        let mut clients = HashMap::new();
+        for target in targets {
+            if !clients.contains_key(&target.lambda_client) {
+                let client = lambda_client(&target.lambda_client).await;
+                clients.insert(target.lambda_client.clone(), client);
+            }
+        }
+        AwsInvoker { clients }
+    }
+
+    fn client(&self, target: &LambdaTarget) -> Result<&Client, GatewayError> {
+        self.clients
+            .get(&target.lambda_client)
+            .ok_or_else(|| GatewayError::Internal(format!("No Lambda client for {}", target.function_name)))
+    }
+}
+
+/// How long before they expire assumed role credentials are refreshed.
+const ASSUMED_ROLE_REFRESH: Duration = Duration::from_secs(300);
+
+async fn lambda_client(settings: &LambdaClientConfig) -> Client {
+    let mut loader = aws_config::defaults(BehaviorVersion::latest());
+    if let Some(region) = &settings.region {
+        loader = loader.region(Region::new(region.clone()));
+    }
+    if let Some(profile) = &settings.profile {
+        loader = loader.profile_name(profile);
+    }
+    // Unset timeouts keep the SDK's defaults.
+    let mut timeouts = TimeoutConfig::builder();
+    if let Some(seconds) = settings.connect_timeout {
+        timeouts = timeouts.connect_timeout(Duration::from_secs(seconds));
+    }
+    if let Some(seconds) = settings.read_timeout {
+        timeouts = timeouts.read_timeout(Duration::from_secs(seconds));
+    }
+    loader = loader.timeout_config(timeouts.build());
+    if settings.retry_mode.is_some() || settings.max_attempts.is_some() {
+        let mut retry = match settings.retry_mode {
+            Some(RetryMode::Adaptive) => RetryConfig::adaptive(),
+            _ => RetryConfig::standard(),
+        };
+        if let Some(max_attempts) = settings.max_attempts {
+            retry = retry.with_max_attempts(max_attempts);
+        }
+        loader = loader.retry_config(retry);
+    }
+    if let Some(pool_size) = settings.pool_max_idle_per_host {
+        let mut hyper_builder = hyper_0_14::Client::builder();
+        hyper_builder.pool_max_idle_per_host(pool_size);
+        loader = loader.http_client(HyperClientBuilder::new().hyper_builder(hyper_builder).build_https());
+    }
+    let sdk_config = loader.load().await;
+    // The endpoint is set on the Lambda client only, so that STS is not sent to it.
+    let mut config = aws_sdk_lambda::config::Builder::from(&sdk_config);
+    if let Some(endpoint_url) = &settings.endpoint_url {
+        config = config.endpoint_url(endpoint_url);
+    }
+    if let Some(assume_role) = &settings.assume_role {
+        config = config
+            .credentials_provider(assume_role_provider(assume_role, &sdk_config).await)
+            .identity_cache(IdentityCache::lazy().buffer_time(ASSUMED_ROLE_REFRESH).build());
+    }
+    Client::from_conf(config.build())
+}
+
+/// Assumes the role with the credentials of `sdk_config`. The provider calls STS on every
+/// lookup; the Lambda client's identity cache keeps the credentials until shortly before they
+/// expire.
+async fn assume_role_provider(assume_role: &AssumeRoleConfig, sdk_config: &SdkConfig) -> AssumeRoleProvider {
+    let mut sts_config = sdk_config.to_builder();
+    if let Some(endpoint_url) = &assume_role.sts_endpoint_url {
+        sts_config.set_endpoint_url(Some(endpoint_url.clone()));
+    }
+    let mut provider = AssumeRoleProvider::builder(&assume_role.role_arn).configure(&sts_config.build());
+    if let Some(external_id) = &assume_role.external_id {
+        provider = provider.external_id(external_id);
+    }
+    if let Some(session_name) = &assume_role.session_name {
+        provider = provider.session_name(session_name);
+    }
+    provider.build().await
+}
+
+impl Invoker for AwsInvoker {
+    fn invoke<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<InvokeResponse, GatewayError>> {
+        async move {
+            let resp = self
+                .client(target)?
+                .invoke()
+                .function_name(target.function_name.as_str())
+                .set_qualifier(target.qualifier.clone())
+                .payload(Blob::new(payload))
+                .send()
+                .await
+                .map_err(GatewayError::from_sdk_error)?;
+            Ok(InvokeResponse {
+                status_code: resp.status_code,
+                function_error: resp.function_error,
+                payload: resp.payload.map(|p| Bytes::from(p.into_inner())).unwrap_or_default(),
+            })
+        }
+        .boxed()
+    }
+
+    fn invoke_event<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), GatewayError>> {
+        async move {
+            self.client(target)?
+                .invoke()
+                .function_name(target.function_name.as_str())
+                .set_qualifier(target.qualifier.clone())
+                .invocation_type(InvocationType::Event)
+                .payload(Blob::new(payload))
+                .send()
+                .await
+                .map_err(GatewayError::from_sdk_error)?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<ResponseStream, GatewayError>> {
+        async move {
+            let resp = self
+                .client(target)?
+                .invoke_with_response_stream()
+                .function_name(target.function_name.as_str())
+                .set_qualifier(target.qualifier.clone())
+                .invocation_type(ResponseStreamingInvocationType::RequestResponse)
+                .payload(Blob::new(payload))
+                .send()
+                .await
+                .map_err(GatewayError::from_sdk_error)?;
+
+            let events = stream::unfold(resp.event_stream, |mut receiver| async move {
+                loop {
+                    let event = match receiver.recv().await {
+                        Ok(Some(PayloadChunk(chunk))) => match chunk.payload {
+                            Some(data) => StreamEvent::Data(Bytes::from(data.into_inner())),
+                            None => continue,
+                        },
+                        Ok(Some(InvokeComplete(complete))) => StreamEvent::Complete(stream_error(&complete)),
+                        Ok(Some(_)) => continue,
+                        Ok(None) => return None,
+                        Err(e) => {
+                            let error = GatewayError::InvokeFailed(DisplayErrorContext(&e).to_string());
+                            return Some((Err(error), receiver));
+                        }
+                    };
+                    return Some((Ok(event), receiver));
+                }
+            });
+
+            Ok(ResponseStream {
+                content_type: resp.response_stream_content_type,
+                events: events.boxed(),
+            })
+        }
+        .boxed()
+    }
+}
+
+/// The function error reported when a response stream completes, if any.
+fn stream_error(complete: &InvokeWithResponseStreamCompleteEvent) -> Option<FunctionError> {
+    let error_code = complete.error_code()?;
+    let details = complete.error_details().unwrap_or_default();
+    Some(FunctionError::parse(error_code, details.as_bytes()))
+}
+
+/// An invocation received by a [`MockInvoker`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Invocation {
+    pub function_name: String,
+    pub qualifier: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+enum MockResponse {
+    Buffered(InvokeResponse),
+    Stream {
+        content_type: Option<String>,
+        events: Vec<Result<StreamEvent, GatewayError>>,
+        /// Whether the stream stays open after the last event instead of ending.
+        stalled: bool,
+    },
+    Error(GatewayError),
+}
+
+/// An in-memory invoker returning canned responses by function name and recording every
+/// invocation. Functions without a response fail with `InvokeFailed`.
+#[derive(Debug, Default)]
+pub struct MockInvoker {
+    responses: HashMap<String, MockResponse>,
+    invocations: Mutex<Vec<Invocation>>,
+    /// Held by every response stream, so that its count tells how many are still open.
+    streams: Arc<()>,
+}
+
+impl MockInvoker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers buffered invocations of `function_name` with `response`.
+    pub fn with_response(mut self, function_name: &str, response: InvokeResponse) -> Self {
+        self.responses
+            .insert(function_name.to_string(), MockResponse::Buffered(response));
+        self
+    }
+
+    /// Answers streaming invocations of `function_name` with `events`. The stream ends after the
+    /// last event, which need not be [`StreamEvent::Complete`].
+    pub fn with_stream(
+        mut self,
+        function_name: &str,
+        content_type: Option<&str>,
+        events: Vec<Result<StreamEvent, GatewayError>>,
+    ) -> Self {
+        let response = MockResponse::Stream {
+            content_type: content_type.map(str::to_string),
+            events,
+            stalled: false,
+        };
+        self.responses.insert(function_name.to_string(), response);
+        self
+    }
+
+    /// Like [`MockInvoker::with_stream`], but the stream then stays open without sending
+    /// anything, like a hung function.
+    pub fn with_stalled_stream(
+        mut self,
+        function_name: &str,
+        content_type: Option<&str>,
+        events: Vec<Result<StreamEvent, GatewayError>>,
+    ) -> Self {
+        let response = MockResponse::Stream {
+            content_type: content_type.map(str::to_string),
+            events,
+            stalled: true,
+        };
+        self.responses.insert(function_name.to_string(), response);
+        self
+    }
+
+    /// Fails every invocation of `function_name` with `error`.
+    pub fn with_error(mut self, function_name: &str, error: GatewayError) -> Self {
+        self.responses
+            .insert(function_name.to_string(), MockResponse::Error(error));
+        self
+    }
+
+    /// The invocations received so far, oldest first.
+    pub fn invocations(&self) -> Vec<Invocation> {
+        self.invocations.lock().unwrap().clone()
+    }
+
+    /// The number of response streams that were returned and not dropped yet.
+    pub fn open_streams(&self) -> usize {
+        Arc::strong_count(&self.streams) - 1
+    }
+
+    fn respond(&self, target: &LambdaTarget, payload: Vec<u8>) -> Result<MockResponse, GatewayError> {
+        self.invocations.lock().unwrap().push(Invocation {
+            function_name: target.function_name.clone(),
+            qualifier: target.qualifier.clone(),
+            payload,
+        });
+        match self.responses.get(&target.function_name) {
+            Some(MockResponse::Error(error)) => Err(error.clone()),
+            Some(response) => Ok(response.clone()),
+            None => Err(GatewayError::InvokeFailed(format!(
+                "Function not found: {}",
+                target.function_name
+            ))),
+        }
+    }
+}
+
+impl Invoker for MockInvoker {
+    fn invoke<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<InvokeResponse, GatewayError>> {
+        let result = match self.respond(target, payload) {
+            Ok(MockResponse::Buffered(response)) => Ok(response),
+            Ok(_) => Err(GatewayError::InvokeFailed(format!(
+                "{} only supports response streaming",
+                target.function_name
+            ))),
+            Err(error) => Err(error),
+        };
+        futures::future::ready(result).boxed()
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<ResponseStream, GatewayError>> {
+        let result = match self.respond(target, payload) {
+            Ok(MockResponse::Stream {
+                content_type,
+                events,
+                stalled,
+            }) => {
+                let events = if stalled {
+                    stream::iter(events).chain(stream::pending()).boxed()
+                } else {
+                    stream::iter(events).boxed()
+                };
+                let open = self.streams.clone();
+                Ok(ResponseStream {
+                    content_type,
+                    events: events
+                        .inspect(move |_| {
+                            let _ = &open;
+                        })
+                        .boxed(),
+                })
+            }
+            Ok(_) => Err(GatewayError::InvokeFailed(format!(
+                "{} does not support response streaming",
+                target.function_name
+            ))),
+            Err(error) => Err(error),
+        };
+        futures::future::ready(result).boxed()
+    }
+
+    fn invoke_event<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), GatewayError>> {
+        futures::future::ready(self.respond(target, payload).map(|_| ())).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("invoker_tests.rs");
+}
@@ -0,0 +1,181 @@
+use super::*;
+use crate::config::{AssumeRoleConfig, PayloadFormat};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::Router;
+use std::sync::Arc;
+
+fn target(function_name: &str) -> LambdaTarget {
+    LambdaTarget {
+        qualifier: Some("live".to_string()),
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..LambdaTarget::for_test(function_name)
+    }
+}
+
+#[test]
+fn test_stream_error() {
+    let complete = InvokeWithResponseStreamCompleteEvent::builder()
+        .error_code("Runtime.ExitError")
+        .error_details(r#"{"errorMessage": "exited", "errorType": "Runtime.ExitError"}"#)
+        .build();
+    let error = stream_error(&complete).unwrap();
+    assert_eq!(error.error_type, "Runtime.ExitError");
+    assert_eq!(error.error_message, "exited");
+
+    let complete = InvokeWithResponseStreamCompleteEvent::builder().build();
+    assert!(stream_error(&complete).is_none());
+}
+
+#[tokio::test]
+async fn test_mock_invoker_records_invocations() {
+    let invoker = MockInvoker::new().with_response("function", InvokeResponse::new("ok"));
+
+    let response = invoker.invoke(&target("function"), b"event".to_vec()).await.unwrap();
+
+    assert_eq!(response.payload, "ok");
+    assert_eq!(
+        invoker.invocations(),
+        vec![Invocation {
+            function_name: "function".to_string(),
+            qualifier: Some("live".to_string()),
+            payload: b"event".to_vec(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_mock_invoker_unknown_function() {
+    let invoker = MockInvoker::new();
+
+    let error = invoker.invoke(&target("missing"), Vec::new()).await.unwrap_err();
+
+    assert!(matches!(error, GatewayError::InvokeFailed(_)));
+    assert_eq!(invoker.invocations().len(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_invoker_stream() {
+    let invoker = MockInvoker::new().with_stream(
+        "function",
+        Some("text/plain"),
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"hello"))), Ok(StreamEvent::Complete(None))],
+    );
+
+    let stream = invoker.invoke_stream(&target("function"), Vec::new()).await.unwrap();
+    let events: Vec<_> = stream.events.collect().await;
+
+    assert_eq!(stream.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(
+        events,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"hello"))), Ok(StreamEvent::Complete(None))]
+    );
+    assert!(invoker.invoke(&target("function"), Vec::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_aws_invoker_clients() {
+    let mut local = target("local");
+    local.lambda_client.endpoint_url = Some("http://localhost:9001".to_string());
+    local.lambda_client.region = Some("us-east-1".to_string());
+    let mut pooled = target("pooled");
+    pooled.lambda_client.region = Some("us-east-1".to_string());
+    pooled.lambda_client.pool_max_idle_per_host = Some(4);
+    let targets = [local.clone(), pooled.clone(), target("other"), local.clone()];
+
+    let invoker = AwsInvoker::new(&targets).await;
+
+    assert_eq!(invoker.clients.len(), 3);
+    let config = invoker.client(&local).unwrap().config();
+    assert_eq!(config.region().map(|r| r.as_ref()), Some("us-east-1"));
+    let mut unknown = target("unknown");
+    unknown.lambda_client.max_attempts = Some(9);
+    assert!(matches!(invoker.client(&unknown), Err(GatewayError::Internal(_))));
+}
+
+const ASSUME_ROLE_RESPONSE: &str = r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>ASIAASSUMED</AccessKeyId>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <SessionToken>session-token</SessionToken>
+      <Expiration>2099-01-01T00:00:00Z</Expiration>
+    </Credentials>
+    <AssumedRoleUser>
+      <AssumedRoleId>AROAEXAMPLE:gateway</AssumedRoleId>
+      <Arn>arn:aws:sts::123456789012:assumed-role/invoker/gateway</Arn>
+    </AssumedRoleUser>
+  </AssumeRoleResult>
+  <ResponseMetadata><RequestId>request-1</RequestId></ResponseMetadata>
+</AssumeRoleResponse>"#;
+
+/// Serves `router` on a local port and returns its URL.
+async fn serve(router: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    url
+}
+
+#[tokio::test]
+async fn test_aws_invoker_assume_role() {
+    // Base credentials, used only to sign the AssumeRole call.
+    std::env::set_var("AWS_ACCESS_KEY_ID", "AKIABASE");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "base-secret");
+    let sts_requests = Arc::new(Mutex::new(Vec::new()));
+    let sts_url = serve(
+        Router::new()
+            .route(
+                "/",
+                post(|State(requests): State<Arc<Mutex<Vec<String>>>>, body: String| async move {
+                    requests.lock().unwrap().push(body);
+                    ASSUME_ROLE_RESPONSE
+                }),
+            )
+            .with_state(sts_requests.clone()),
+    )
+    .await;
+    let lambda_requests = Arc::new(Mutex::new(Vec::new()));
+    let lambda_url = serve(
+        Router::new()
+            .route(
+                "/2015-03-31/functions/:function_name/invocations",
+                post(|State(requests): State<Arc<Mutex<Vec<HeaderMap>>>>, headers: HeaderMap| async move {
+                    requests.lock().unwrap().push(headers);
+                    "ok"
+                }),
+            )
+            .with_state(lambda_requests.clone()),
+    )
+    .await;
+    let mut target = target("function");
+    target.lambda_client.region = Some("us-east-1".to_string());
+    target.lambda_client.endpoint_url = Some(lambda_url);
+    target.lambda_client.max_attempts = Some(1);
+    target.lambda_client.assume_role = Some(AssumeRoleConfig {
+        role_arn: "arn:aws:iam::123456789012:role/invoker".to_string(),
+        external_id: Some("external-1".to_string()),
+        session_name: Some("gateway".to_string()),
+        sts_endpoint_url: Some(sts_url),
+    });
+
+    let invoker = AwsInvoker::new([&target]).await;
+    for _ in 0..2 {
+        let response = invoker.invoke(&target, b"{}".to_vec()).await.unwrap();
+        assert_eq!(response.payload, "ok");
+    }
+
+    let sts_requests = sts_requests.lock().unwrap();
+    assert_eq!(sts_requests.len(), 1, "credentials are cached");
+    assert!(sts_requests[0].contains("Action=AssumeRole"));
+    assert!(sts_requests[0].contains("RoleArn=arn%3Aaws%3Aiam%3A%3A123456789012%3Arole%2Finvoker"));
+    assert!(sts_requests[0].contains("ExternalId=external-1"));
+    assert!(sts_requests[0].contains("RoleSessionName=gateway"));
+    let lambda_requests = lambda_requests.lock().unwrap();
+    assert_eq!(lambda_requests.len(), 2);
+    for headers in lambda_requests.iter() {
+        assert!(headers["authorization"].to_str().unwrap().contains("Credential=ASIAASSUMED/"));
+        assert_eq!(headers["x-amz-security-token"], "session-token");
+    }
+}
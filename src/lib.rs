@@ -1,102 +1,177 @@
+pub mod body;
+pub mod canary;
 pub mod config;
+pub mod error;
+pub mod event;
+pub mod failover;
+pub mod fallback;
+pub mod invoker;
+pub mod local;
+pub mod media;
+pub mod metrics;
+pub mod mirror;
+pub mod router;
+pub mod sse;
+pub mod template;
 
 #[cfg(test)]
 mod tests {
     include!("lib_tests.rs");
 }
 
-use crate::config::{Config, LambdaInvokeMode};
-use aws_config::BehaviorVersion;
-use aws_sdk_lambda::types::InvokeWithResponseStreamResponseEvent::{InvokeComplete, PayloadChunk};
-use aws_sdk_lambda::types::{InvokeResponseStreamUpdate, ResponseStreamingInvocationType};
-use aws_sdk_lambda::Client;
-use aws_smithy_types::Blob;
+use crate::body::{BodySender, ClientDisconnected};
+use crate::canary::VARIANT_HEADER;
+use crate::config::{Backend, Config, LambdaInvokeMode, PayloadFormat, PreludeDetection};
+use crate::error::{FunctionError, GatewayError};
+use crate::event::HttpRequest;
+use crate::failover::FailoverInvoker;
+use crate::fallback::{Fallback, FallbackAction, FALLBACK_HEADER};
+use crate::invoker::{AwsInvoker, InvokeResponse, Invoker, ResponseStream, StreamEvent};
+use crate::local::LocalInvoker;
+use crate::metrics::Metrics;
+use crate::mirror::Mirror;
+use crate::router::{HostRouter, LambdaTarget, RouteLookup};
+use crate::sse::EventBuffer;
 use axum::body::Body;
 use axum::{
     body::Bytes,
-    extract::{Path, Query, State},
-    http::{HeaderMap, Method, StatusCode},
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, Version},
     response::{IntoResponse, Response},
     routing::any,
     routing::get,
     Router,
 };
 use base64::Engine;
-use futures_util::stream::StreamExt;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use http_body::Frame;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::collections::HashMap;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 use tower_http::trace::TraceLayer;
+use tracing::Instrument;
+
+/// Trailer carrying the error code of a response stream that failed after the headers were sent.
+const ERROR_CODE_TRAILER: &str = "x-lambda-error-code";
+
+/// Content type of response streams that start with a metadata prelude.
+const HTTP_INTEGRATION_RESPONSE_CONTENT_TYPE: &str = "application/vnd.awslambda.http-integration-response";
+
+/// Eight NUL bytes separate the metadata prelude from the response body.
+const PRELUDE_DELIMITER: &[u8] = &[0; 8];
 
 #[derive(Clone)]
 pub struct ApplicationState {
-    client: Client,
+    invoker: Arc<dyn Invoker>,
     config: Config,
+    hosts: Arc<HostRouter>,
+    metrics: Arc<Metrics>,
+}
+
+impl ApplicationState {
+    /// Fails when the route configuration is invalid. Invocations of targets with `regions` fail
+    /// over between them.
+    pub fn new(config: Config, invoker: Arc<dyn Invoker>) -> Result<Self, String> {
+        let hosts = HostRouter::new(&config)?;
+        Ok(ApplicationState {
+            invoker: Arc::new(FailoverInvoker::new(invoker, &config)),
+            config,
+            hosts: Arc::new(hosts),
+            metrics: Arc::new(Metrics::default()),
+        })
+    }
+}
+
+/// The gateway's routes, invoking functions through the invoker of `state`.
+pub fn app(state: ApplicationState) -> Router {
+    Router::new()
+        .route("/healthz", get(health))
+        .route("/", any(handler))
+        .route("/*path", any(handler))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state)
+}
+
+/// The Prometheus metrics of `state`, served on `metrics_addr` apart from the gateway's routes so
+/// that it neither shadows a function path nor bypasses authentication.
+pub fn metrics_app(state: ApplicationState) -> Router {
+    Router::new().route("/metrics", get(metrics)).with_state(state)
 }
 
 pub async fn run_app() {
     tracing_subscriber::fmt::init();
 
     let config = Config::load("config.yaml");
-    let aws_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-    let client = Client::new(&aws_config);
+    let invoker: Arc<dyn Invoker> = match config.backend {
+        Backend::Aws => {
+            let hosts = HostRouter::new(&config).unwrap_or_else(|e| panic!("Invalid route configuration: {}", e));
+            let targets: Vec<_> = hosts.targets().flat_map(failover::targets).collect();
+            Arc::new(AwsInvoker::new(&targets).await)
+        }
+        Backend::Local => {
+            let local_runtime = config
+                .local_runtime
+                .as_ref()
+                .unwrap_or_else(|| panic!("The Local backend requires local_runtime"));
+            let invoker = LocalInvoker::start(local_runtime, &config.lambda_function_name)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to start {}: {}", local_runtime.command, e));
+            Arc::new(invoker)
+        }
+    };
 
-    let app_state = ApplicationState { client, config };
+    let app_state =
+        ApplicationState::new(config, invoker).unwrap_or_else(|e| panic!("Invalid route configuration: {}", e));
+    let app = app(app_state.clone());
 
-    let app = Router::new()
-        .route("/healthz", get(health))
-        .route("/", any(handler))
-        .route("/*path", any(handler))
-        .layer(TraceLayer::new_for_http())
-        .with_state(app_state.clone());
+    if let Some(metrics_addr) = &app_state.config.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(metrics_addr).await.unwrap();
+        tracing::info!("Serving metrics on {}", metrics_addr);
+        let metrics_app = metrics_app(app_state.clone());
+        tokio::spawn(async move { axum::serve(listener, metrics_app).await.unwrap() });
+    }
 
     let addr = &app_state.config.addr;
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     tracing::info!("Listening on {}", addr);
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
 }
 
 async fn health() -> impl IntoResponse {
     StatusCode::OK
 }
 
+async fn metrics(State(state): State<ApplicationState>) -> impl IntoResponse {
+    ([("content-type", "text/plain; version=0.0.4")], state.metrics.render())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handler(
     path: Option<Path<String>>,
-    Query(query_string_parameters): Query<HashMap<String, String>>,
     State(state): State<ApplicationState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     method: Method,
+    version: Version,
+    uri: Uri,
     headers: HeaderMap,
     body: Bytes,
 ) -> Response {
-    let client = &state.client;
-    let config = &state.config;
+    let host = headers
+        .get("host")
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| uri.authority().map(|a| a.as_str()));
+    let vhost = state.hosts.resolve(host);
     let path = "/".to_string() + path.map(|p| p.0).unwrap_or_default().as_str();
 
-    let http_method = method.to_string();
-
-    let content_type = headers
-        .get("content-type")
-        .and_then(|v| v.to_str().ok().map(Some).flatten())
-        .unwrap_or_default();
-
-    let is_base64_encoded = match content_type {
-        "application/json" => false,
-        "application/xml" => false,
-        "application/javascript" => false,
-        _ if content_type.starts_with("text/") => false,
-        _ => true,
-    };
-
-    let body = if is_base64_encoded {
-        base64::engine::general_purpose::STANDARD.encode(body)
-    } else {
-        String::from_utf8_lossy(&body).to_string()
-    };
-
-    match config.auth_mode {
-        config::AuthMode::Open => {}
+    let api_key = match vhost.auth_mode {
+        config::AuthMode::Open => None,
         config::AuthMode::ApiKey => {
             let api_key = headers
                 .get("x-api-key")
@@ -108,77 +183,242 @@ async fn handler(
                 })
                 .unwrap_or_default();
 
-            if !config.api_keys.contains(api_key) {
+            if !vhost.api_keys.contains(api_key) {
                 return Response::builder()
                     .status(StatusCode::UNAUTHORIZED)
                     .body(Body::empty())
                     .unwrap();
             }
+            Some(api_key)
         }
-    }
-
-    let lambda_request_body = json!({
-        "httpMethod": http_method,
-        "headers": to_string_map(&headers),
-        "path": path,
-        "queryStringParameters": query_string_parameters,
-        "isBase64Encoded": is_base64_encoded,
-        "body": body,
-        "requestContext": {
-            "elb": {
-                "targetGroupArn": "",
-            },
-        },
-    })
-    .to_string();
+    };
 
-    let resp = match config.lambda_invoke_mode {
-        LambdaInvokeMode::Buffered => {
-            let resp = client
-                .invoke()
-                .function_name(config.lambda_function_name.as_str())
-                .payload(Blob::new(lambda_request_body))
-                .send()
-                .await
+    let route = match vhost.routes.find(&method, &path) {
+        RouteLookup::Matched(route) => route,
+        RouteLookup::MethodNotAllowed => {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .body(Body::empty())
                 .unwrap();
-            handle_buffered_response(resp).await
         }
-        LambdaInvokeMode::ResponseStream => {
-            let resp = client
-                .invoke_with_response_stream()
-                .function_name(config.lambda_function_name.as_str())
-                .invocation_type(ResponseStreamingInvocationType::RequestResponse)
-                .payload(Blob::new(lambda_request_body))
-                .send()
-                .await
+        RouteLookup::NotFound => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
                 .unwrap();
-            handle_streaming_response(resp).await
         }
     };
+    let variant = route.canary.map(|canary| canary.select(&headers));
+    let target = variant.map_or(route.target, |variant| &variant.target);
+
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let query = event::parse_query(uri.query());
+    let request = HttpRequest {
+        request_id: &request_id,
+        method: &method,
+        version,
+        path: &path,
+        raw_path: uri.path(),
+        headers: &headers,
+        raw_query: uri.query().unwrap_or_default(),
+        query: &query,
+        body: &body,
+        source_ip: connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        api_key,
+        resource: route.resource,
+        path_parameters: &route.path_parameters,
+    };
 
-    resp
+    let span = tracing::info_span!(
+        "invoke",
+        request_id,
+        function_name = %target.function_name,
+        variant = variant.map(|variant| variant.name.as_str())
+    );
+    let mirror = route.mirror.filter(|mirror| mirror.sample());
+    let started = Instant::now();
+    let mut response = match invoke(&state, target, mirror, route.fallback, &request)
+        .instrument(span.clone())
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => {
+            span.in_scope(|| tracing::error!(error = %e, "Request failed"));
+            e.into_response(&request_id, &state.config)
+        }
+    };
+    if mirror.is_some() {
+        // Logged like the mirror's outcome, so the two can be compared by request id.
+        let latency_ms = started.elapsed().as_millis() as u64;
+        span.in_scope(|| tracing::info!(status = response.status().as_u16(), latency_ms, "Request completed"));
+    }
+    if let Some(variant) = variant {
+        state
+            .metrics
+            .record_variant_response(&target.function_name, &variant.name, response.status());
+        response
+            .headers_mut()
+            .insert(VARIANT_HEADER, variant.header_value.clone());
+    }
+    response
 }
 
-fn to_string_map(headers: &HeaderMap) -> HashMap<String, String> {
-    headers
-        .iter()
-        .map(|(k, v)| {
-            (
-                k.as_str().to_owned(),
-                String::from_utf8_lossy(v.as_bytes()).into_owned(),
-            )
-        })
-        .collect()
+async fn invoke(
+    state: &ApplicationState,
+    target: &LambdaTarget,
+    mirror: Option<&Arc<Mirror>>,
+    fallback: Option<&Fallback>,
+    request: &HttpRequest<'_>,
+) -> Result<Response, GatewayError> {
+    let lambda_request_body = event::build_event(target, request, &state.config).map_err(GatewayError::Internal)?;
+    if let Some(mirror) = mirror {
+        if !mirror.send(state.invoker.clone(), request.request_id, &lambda_request_body) {
+            tracing::debug!("Mirror at max_in_flight, not mirroring the request");
+            state.metrics.record_mirror_dropped();
+        }
+    }
+    let fallback_body = fallback.and_then(Fallback::target).map(|_| lambda_request_body.clone());
+
+    let (error, fallback) = match invoke_target(state, target, lambda_request_body, fallback, request).await {
+        Err(error) => match fallback {
+            Some(fallback) if fallback.handles(&error) => (error, fallback),
+            _ => return Err(error),
+        },
+        response => return response,
+    };
+    tracing::warn!(error = %error, "Serving the fallback");
+    let mut response = match &fallback.action {
+        FallbackAction::Function(fallback_target) => {
+            let payload = fallback_body.unwrap_or_default();
+            match invoke_target(state, fallback_target, payload, None, request).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!(error = %e, function_name = %fallback_target.function_name, "Fallback failed");
+                    return Err(error);
+                }
+            }
+        }
+        FallbackAction::Response(response) => response.to_response(),
+    };
+    response
+        .headers_mut()
+        .insert(FALLBACK_HEADER, HeaderValue::from_static(error.kind()));
+    Ok(response)
+}
+
+/// Invokes `target` with the event `payload` and translates its result into a response.
+///
+/// A function error handled by `fallback` is returned as an error before the result is
+/// translated, so that Raw routes and response templates do not turn it into a response.
+async fn invoke_target(
+    state: &ApplicationState,
+    target: &LambdaTarget,
+    lambda_request_body: Vec<u8>,
+    fallback: Option<&Fallback>,
+    request: &HttpRequest<'_>,
+) -> Result<Response, GatewayError> {
+    match target.invoke_mode {
+        LambdaInvokeMode::Buffered => {
+            let resp = state.invoker.invoke(target, lambda_request_body).await?;
+            if let (Some(error_code), Some(fallback)) = (resp.function_error.as_deref(), fallback) {
+                let error = GatewayError::FunctionError(FunctionError::parse(error_code, &resp.payload));
+                if fallback.handles(&error) {
+                    return Err(error);
+                }
+            }
+            handle_buffered_response(resp, target).await
+        }
+        LambdaInvokeMode::ResponseStream => {
+            let resp = state.invoker.invoke_stream(target, lambda_request_body).await?;
+            let trailers = state.config.stream_error_trailers && accepts_trailers(request.headers);
+            handle_streaming_response(resp, target, &state.config, trailers, state.metrics.clone()).await
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct LambdaResponse {
-    status_code: u16,
+    /// Optional for payload format 2.0, where it defaults to 200.
+    status_code: Option<u16>,
     status_description: Option<String>,
     is_base64_encoded: Option<bool>,
     headers: Option<HashMap<String, String>>,
-    body: String,
+    /// Used by ALB multi-value mode and payload format 1.0. Sent in addition to `headers`.
+    multi_value_headers: Option<HashMap<String, Vec<String>>>,
+    /// Payload format 2.0 returns cookies separately from the headers.
+    cookies: Option<Vec<String>>,
+    body: Option<String>,
+}
+
+impl LambdaResponse {
+    fn from_payload(payload: &[u8]) -> Result<Self, GatewayError> {
+        serde_json::from_slice(payload)
+            .map_err(|e| GatewayError::InvalidResponse(format!("Failed to parse Lambda response: {}", e)))
+    }
+
+    /// Parses a payload format 2.0 response. Any payload that is not an object with a
+    /// `statusCode` is itself the response body, returned as JSON with status 200.
+    fn from_v2_payload(payload: &[u8]) -> Result<Self, GatewayError> {
+        match serde_json::from_slice::<serde_json::Value>(payload) {
+            Ok(value) if value.get("statusCode").is_some() => serde_json::from_value(value)
+                .map_err(|e| GatewayError::InvalidResponse(format!("Failed to parse Lambda response: {}", e))),
+            _ => Ok(LambdaResponse {
+                status_code: Some(200),
+                status_description: None,
+                is_base64_encoded: Some(false),
+                headers: Some(HashMap::from([(
+                    "content-type".to_string(),
+                    "application/json".to_string(),
+                )])),
+                multi_value_headers: None,
+                cookies: None,
+                body: Some(String::from_utf8_lossy(payload).into_owned()),
+            }),
+        }
+    }
+
+    fn into_response(self) -> Result<Response, GatewayError> {
+        let status_code = self
+            .status_code
+            .ok_or_else(|| GatewayError::InvalidResponse("Lambda response has no statusCode".to_string()))?;
+        let status_code = StatusCode::from_u16(status_code).map_err(|_| {
+            GatewayError::InvalidResponse(format!("Invalid statusCode in Lambda response: {}", status_code))
+        })?;
+
+        // Build the response using the extracted information
+        let mut resp_builder = Response::builder().status(status_code);
+
+        if let Some(headers) = self.headers {
+            for (key, value) in headers {
+                resp_builder = resp_builder.header(key, value);
+            }
+        }
+
+        if let Some(headers) = self.multi_value_headers {
+            for (key, values) in headers {
+                for value in values {
+                    resp_builder = resp_builder.header(&key, value);
+                }
+            }
+        }
+
+        for cookie in self.cookies.unwrap_or_default() {
+            resp_builder = resp_builder.header("set-cookie", cookie);
+        }
+
+        let body = self.body.unwrap_or_default();
+        let body = if self.is_base64_encoded.unwrap_or(false) {
+            base64::engine::general_purpose::STANDARD
+                .decode(body)
+                .map_err(|e| GatewayError::InvalidResponse(format!("Invalid base64 body in Lambda response: {}", e)))?
+        } else {
+            body.into_bytes()
+        };
+        resp_builder
+            .body(Body::from(body))
+            .map_err(|e| GatewayError::InvalidResponse(format!("Invalid header in Lambda response: {}", e)))
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -187,99 +427,118 @@ struct MetadataPrelude {
     #[serde(with = "http_serde::status_code")]
     /// The HTTP status code.
     pub status_code: StatusCode,
-    #[serde(with = "http_serde::header_map")]
+    #[serde(default, with = "http_serde::header_map")]
     /// The HTTP headers.
     pub headers: HeaderMap,
+    #[serde(default)]
     /// The HTTP cookies.
     pub cookies: Vec<String>,
 }
 
-async fn handle_buffered_response(resp: aws_sdk_lambda::operation::invoke::InvokeOutput) -> Response {
-    // Parse the InvokeOutput payload to extract the LambdaResponse
-    let payload = resp.payload().unwrap().as_ref().to_vec();
-    let lambda_response: LambdaResponse = serde_json::from_slice(&payload).unwrap();
-
-    // Build the response using the extracted information
-    let mut resp_builder = Response::builder().status(StatusCode::from_u16(lambda_response.status_code).unwrap());
+async fn handle_buffered_response(resp: InvokeResponse, target: &LambdaTarget) -> Result<Response, GatewayError> {
+    let templates = target.templates.as_ref().filter(|t| t.has_response());
+    match target.payload_format {
+        PayloadFormat::Raw => return handle_raw_response(resp, &target.raw_content_type),
+        // Without a response template the result is passed through, like a non-proxy integration.
+        PayloadFormat::Template if templates.is_none() => return handle_raw_response(resp, &target.raw_content_type),
+        _ => {}
+    }
 
-    if let Some(headers) = lambda_response.headers {
-        for (key, value) in headers {
-            resp_builder = resp_builder.header(key, value);
+    let payload = resp.payload.as_ref();
+    let lambda_response = match (&target.payload_format, templates) {
+        // Response templates see the function error and map it themselves.
+        (PayloadFormat::Template, Some(templates)) => {
+            let context = event::response_template_context(resp.status_code, resp.function_error.as_deref(), payload);
+            let rendered = templates
+                .render_response(context)
+                .map_err(GatewayError::InvalidResponse)?;
+            LambdaResponse::from_payload(rendered.as_bytes())?
         }
-    }
+        _ if resp.function_error.is_some() => {
+            let error_code = resp.function_error.as_deref().unwrap_or_default();
+            return Err(GatewayError::FunctionError(FunctionError::parse(error_code, payload)));
+        }
+        (PayloadFormat::ApiGatewayV2, _) => LambdaResponse::from_v2_payload(payload)?,
+        _ => LambdaResponse::from_payload(payload)?,
+    };
+
+    lambda_response.into_response()
+}
 
-    let body = if lambda_response.is_base64_encoded.unwrap_or(false) {
-        base64::engine::general_purpose::STANDARD
-            .decode(lambda_response.body)
-            .unwrap()
+/// Returns the function result unchanged. Function errors are reported as `502 Bad Gateway`.
+fn handle_raw_response(resp: InvokeResponse, content_type: &str) -> Result<Response, GatewayError> {
+    let status = if resp.function_error.is_some() {
+        StatusCode::BAD_GATEWAY
     } else {
-        lambda_response.body.into_bytes()
+        StatusCode::OK
     };
-    resp_builder.body(Body::from(body)).unwrap()
+
+    Response::builder()
+        .status(status)
+        .header("content-type", content_type)
+        .body(Body::from(resp.payload))
+        .map_err(|e| GatewayError::Internal(format!("Invalid raw_content_type: {}", e)))
 }
 
 async fn handle_streaming_response(
-    mut resp: aws_sdk_lambda::operation::invoke_with_response_stream::InvokeWithResponseStreamOutput,
-) -> Response {
-    let (tx, rx) = mpsc::channel(1);
-    let mut metadata_buffer = Vec::new();
-    let mut metadata_prelude: Option<MetadataPrelude> = None;
-    let mut remaining_data = Vec::new();
-
-    // Step 1: Detect if metadata exists and get the first chunk
-    let (has_metadata, first_chunk) = detect_metadata(&mut resp).await;
+    resp: ResponseStream,
+    target: &LambdaTarget,
+    config: &Config,
+    trailers: bool,
+    metrics: Arc<Metrics>,
+) -> Result<Response, GatewayError> {
+    let (tx, body) = body::channel(config.stream_buffer_size);
+    // Raw and templated responses never carry a metadata prelude.
+    let passthrough = matches!(target.payload_format, PayloadFormat::Raw | PayloadFormat::Template);
+    let has_prelude = !passthrough
+        && match target.prelude_detection {
+            PreludeDetection::ContentType => {
+                resp.content_type.as_deref() == Some(HTTP_INTEGRATION_RESPONSE_CONTENT_TYPE)
+            }
+            PreludeDetection::Always => true,
+            PreludeDetection::Never => false,
+        };
+
+    // Until the headers are sent, a timeout still becomes a 504 response.
+    let limits = StreamLimits::new(target);
+    let mut events = resp.events;
+    let (metadata_prelude, remaining_data) = if has_prelude {
+        let (prelude, remaining) = limits
+            .apply(collect_metadata(&mut events, config.max_prelude_size))
+            .await??;
+        (Some(prelude), remaining)
+    } else if target.sse {
+        // Send the headers right away so that keepalives flow while the function is silent.
+        (None, Vec::new())
+    } else {
+        (None, limits.apply(first_chunk(&mut events)).await??.unwrap_or_default())
+    };
 
-    // Step 2: Process the first chunk
-    if let Some(chunk) = first_chunk {
-        if has_metadata {
-            metadata_buffer.extend_from_slice(&chunk);
-            (metadata_prelude, remaining_data) = collect_metadata(&mut resp, &mut metadata_buffer).await;
-        } else {
-            // No metadata prelude, treat first chunk as payload
-            remaining_data = chunk;
-        }
-    }
+    let event_buffer = target.sse.then(EventBuffer::default);
+    let keepalive_interval = target.sse_keepalive_interval.filter(|_| target.sse);
 
     // Spawn task to handle remaining stream
-    tokio::spawn(async move {
-        // Send remaining data after metadata first
-        if !remaining_data.is_empty() {
-            let stream_update = InvokeResponseStreamUpdate::builder()
-                .payload(Blob::new(remaining_data))
-                .build();
-            let _ = tx.send(PayloadChunk(stream_update)).await;
-        }
-
-        while let Some(event) = resp.event_stream.recv().await.unwrap() {
-            match event {
-                PayloadChunk(chunk) => {
-                    if let Some(data) = chunk.payload() {
-                        let stream_update = InvokeResponseStreamUpdate::builder().payload(data.clone()).build();
-                        let _ = tx.send(PayloadChunk(stream_update)).await;
-                    }
-                }
-                InvokeComplete(_) => {
-                    let _ = tx.send(event).await;
-                }
-                _ => {}
+    tokio::spawn(
+        async move {
+            if forward_stream(
+                events,
+                remaining_data,
+                &tx,
+                limits,
+                trailers,
+                event_buffer,
+                keepalive_interval,
+            )
+            .await
+            .is_err()
+            {
+                // Dropping the event stream cancels the invocation.
+                tracing::info!("Client disconnected, cancelling the Lambda response stream");
+                metrics.record_stream_client_disconnect();
             }
         }
-    });
-
-    let stream = ReceiverStream::new(rx).map(|event| {
-        match event {
-            PayloadChunk(chunk) => {
-                if let Some(data) = chunk.payload() {
-                    let bytes = data.clone().into_inner();
-                    Ok::<_, std::convert::Infallible>(Bytes::from(bytes))
-                } else {
-                    Ok(Bytes::default())
-                }
-            },
-            InvokeComplete(_) => Ok(Bytes::default()),
-            _ => Ok(Bytes::default()), // Handle other event types
-        }
-    });
+        .in_current_span(),
+    );
 
     let mut resp_builder = Response::builder();
 
@@ -298,74 +557,238 @@ async fn handle_streaming_response(
     } else {
         // Default response if no metadata
         resp_builder = resp_builder.status(StatusCode::OK);
-        resp_builder = resp_builder.header("content-type", "application/octet-stream");
+        let content_type = if target.sse {
+            "text/event-stream"
+        } else if passthrough {
+            target.raw_content_type.as_str()
+        } else {
+            "application/octet-stream"
+        };
+        resp_builder = resp_builder.header("content-type", content_type);
+    }
+
+    if trailers {
+        resp_builder = resp_builder.header("trailer", ERROR_CODE_TRAILER);
+    }
+
+    let mut response = resp_builder
+        .body(body)
+        .map_err(|e| GatewayError::InvalidResponse(format!("Invalid header in metadata prelude: {}", e)))?;
+    if target.sse {
+        // Keep caches and buffering proxies from holding back events.
+        let headers = response.headers_mut();
+        headers
+            .entry("cache-control")
+            .or_insert(HeaderValue::from_static("no-cache"));
+        headers.insert("x-accel-buffering", HeaderValue::from_static("no"));
     }
+    Ok(response)
+}
 
-    resp_builder.body(Body::from_stream(stream)).unwrap()
+/// The `stream_idle_timeout` and `stream_max_duration` of a streamed response.
+#[derive(Clone, Copy, Debug)]
+struct StreamLimits {
+    idle_timeout: Option<Duration>,
+    max_duration: Option<Duration>,
+    deadline: Option<Instant>,
 }
 
-async fn detect_metadata(
-    resp: &mut aws_sdk_lambda::operation::invoke_with_response_stream::InvokeWithResponseStreamOutput,
-) -> (bool, Option<Vec<u8>>) {
-    if let Ok(Some(event)) = resp.event_stream.recv().await {
-        if let PayloadChunk(chunk) = event {
-            if let Some(data) = chunk.payload() {
-                let bytes = data.clone().into_inner();
-                let has_metadata = !bytes.is_empty() && bytes[0] == b'{';
-                return (has_metadata, Some(bytes));
-            }
+impl StreamLimits {
+    fn new(target: &LambdaTarget) -> Self {
+        StreamLimits {
+            idle_timeout: target.stream_idle_timeout,
+            max_duration: target.stream_max_duration,
+            deadline: target.stream_max_duration.map(|d| Instant::now() + d),
         }
     }
-    (false, None)
-}
 
-async fn collect_metadata(
-    resp: &mut aws_sdk_lambda::operation::invoke_with_response_stream::InvokeWithResponseStreamOutput,
-    metadata_buffer: &mut Vec<u8>,
-) -> (Option<MetadataPrelude>, Vec<u8>) {
-    let mut metadata_prelude = None;
-    let mut remaining_data = Vec::new();
-
-    // Process the metadata_buffer first
-    let (prelude, remaining) = process_buffer(metadata_buffer);
-    if let Some(p) = prelude {
-        return (Some(p), remaining);
+    /// Awaits `future` unless the stream goes idle or reaches its deadline first.
+    async fn apply<F: Future>(&self, future: F) -> Result<F::Output, GatewayError> {
+        let idle_deadline = self.idle_timeout.map(|d| Instant::now() + d);
+        let timeout_at = match (idle_deadline, self.deadline) {
+            (Some(idle), Some(deadline)) => Some(idle.min(deadline)),
+            (idle, deadline) => idle.or(deadline),
+        };
+        let Some(timeout_at) = timeout_at else {
+            return Ok(future.await);
+        };
+
+        tokio::time::timeout_at(timeout_at, future)
+            .await
+            .map_err(|_| match self.max_duration {
+                Some(max_duration) if Some(timeout_at) == self.deadline => GatewayError::Timeout(format!(
+                    "Response stream exceeded stream_max_duration of {}s",
+                    max_duration.as_secs()
+                )),
+                _ => GatewayError::Timeout(format!(
+                    "No data from the function within stream_idle_timeout of {}s",
+                    self.idle_timeout.unwrap_or_default().as_secs()
+                )),
+            })
     }
+}
 
-    // If metadata is not complete, continue processing the stream
-    while let Ok(Some(event)) = resp.event_stream.recv().await {
-        if let PayloadChunk(chunk) = event {
-            if let Some(data) = chunk.payload() {
-                let bytes = data.clone().into_inner();
-                metadata_buffer.extend_from_slice(&bytes);
-                let (prelude, remaining) = process_buffer(metadata_buffer);
-                if let Some(p) = prelude {
-                    metadata_prelude = Some(p);
-                    remaining_data = remaining;
-                    break;
+/// Forwards the Lambda event stream to the response body until it ends or the client disconnects.
+#[allow(clippy::too_many_arguments)]
+async fn forward_stream(
+    mut stream: BoxStream<'static, Result<StreamEvent, GatewayError>>,
+    remaining_data: Vec<u8>,
+    tx: &BodySender,
+    limits: StreamLimits,
+    trailers: bool,
+    mut events: Option<EventBuffer>,
+    keepalive_interval: Option<Duration>,
+) -> Result<(), ClientDisconnected> {
+    // Send remaining data after metadata first
+    send_data(tx, &mut events, Bytes::from(remaining_data)).await?;
+
+    let mut timeout = None;
+    loop {
+        // A keepalive must not land inside an event that was flushed in part.
+        let keepalive_interval = keepalive_interval.filter(|_| events.as_ref().is_some_and(EventBuffer::at_boundary));
+        let recv = limits.apply(stream.next());
+        tokio::pin!(recv);
+        let event = loop {
+            tokio::select! {
+                // Stop waiting for slow streams as soon as the body is dropped.
+                _ = tx.closed() => return Err(ClientDisconnected),
+                _ = tokio::time::sleep(keepalive_interval.unwrap_or_default()), if keepalive_interval.is_some() => {
+                    tx.send_data(Bytes::from_static(sse::KEEPALIVE)).await?;
                 }
+                event = &mut recv => break event,
             }
-        }
+        };
+        let error = match event {
+            // The data sent so far is intact, so a timeout ends the body like the end of the stream.
+            Err(error) => {
+                tracing::error!(error = %error, "Ending the response stream");
+                timeout = Some(error);
+                break;
+            }
+            Ok(Some(Ok(StreamEvent::Data(data)))) => {
+                send_data(tx, &mut events, data).await?;
+                continue;
+            }
+            Ok(Some(Ok(StreamEvent::Complete(Some(error))))) => {
+                tracing::error!(error = %error, "Function failed while streaming the response");
+                GatewayError::FunctionError(error)
+            }
+            Ok(Some(Ok(StreamEvent::Complete(None)))) | Ok(None) => break,
+            Ok(Some(Err(error))) => {
+                tracing::error!(error = %error, "Failed to read Lambda response stream");
+                error
+            }
+        };
+
+        // Headers are already sent, so the error either ends the body with a trailer or
+        // aborts it, which clients see as a truncated response.
+        let frame = if trailers {
+            Ok(Frame::trailers(error_trailers(&error)))
+        } else {
+            Err(error)
+        };
+        return tx.send(frame).await;
+    }
+
+    if let Some(data) = events.as_mut().and_then(EventBuffer::finish) {
+        tx.send_data(data).await?;
+    }
+    match timeout {
+        Some(error) if trailers => tx.send(Ok(Frame::trailers(error_trailers(&error)))).await,
+        _ => Ok(()),
+    }
+}
+
+/// Sends streamed data to the body. SSE responses only pass on complete events.
+async fn send_data(tx: &BodySender, events: &mut Option<EventBuffer>, data: Bytes) -> Result<(), ClientDisconnected> {
+    let data = match events.as_mut() {
+        Some(events) => events.push(&data),
+        None => Some(data),
+    };
+    match data {
+        Some(data) if !data.is_empty() => tx.send_data(data).await,
+        _ => Ok(()),
     }
-    (metadata_prelude, remaining_data)
 }
 
-fn process_buffer(buffer: &[u8]) -> (Option<MetadataPrelude>, Vec<u8>) {
-    let mut null_count = 0;
-    for (i, &byte) in buffer.iter().enumerate() {
-        if byte == 0 {
-            null_count += 1;
-            if null_count == 8 {
-                let metadata_str = String::from_utf8_lossy(&buffer[..i]);
-                let metadata_prelude = serde_json::from_str(&metadata_str).unwrap_or_default();
-                tracing::debug!(metadata_prelude=?metadata_prelude);
-                // Save remaining data after metadata
-                let remaining_data = buffer[i + 1..].to_vec();
-                return (Some(metadata_prelude), remaining_data);
+/// The trailers ending a response stream that failed after the headers were sent.
+fn error_trailers(error: &GatewayError) -> HeaderMap {
+    let error_code = match error {
+        GatewayError::FunctionError(e) => e.error_type.as_str(),
+        _ => error.kind(),
+    };
+    let mut trailers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(error_code) {
+        trailers.insert(ERROR_CODE_TRAILER, value);
+    }
+    trailers
+}
+
+/// Whether the client asked for trailers with `TE: trailers`. HTTP/1 responses drop trailers
+/// otherwise, which would make a failed stream look complete.
+fn accepts_trailers(headers: &HeaderMap) -> bool {
+    headers
+        .get_all("te")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// Reads the first chunk of a response stream without a metadata prelude.
+async fn first_chunk(
+    events: &mut BoxStream<'static, Result<StreamEvent, GatewayError>>,
+) -> Result<Option<Vec<u8>>, GatewayError> {
+    match events.next().await.transpose()? {
+        Some(StreamEvent::Data(data)) => Ok(Some(data.into())),
+        // The function failed before streaming anything, so the error can still set the status.
+        Some(StreamEvent::Complete(Some(error))) => Err(GatewayError::FunctionError(error)),
+        Some(StreamEvent::Complete(None)) | None => Ok(None),
+    }
+}
+
+/// Reads the metadata prelude and returns it with the payload data that followed it.
+async fn collect_metadata(
+    events: &mut BoxStream<'static, Result<StreamEvent, GatewayError>>,
+    max_prelude_size: usize,
+) -> Result<(MetadataPrelude, Vec<u8>), GatewayError> {
+    let mut metadata_buffer = Vec::new();
+    loop {
+        match events.next().await.transpose()? {
+            Some(StreamEvent::Data(data)) => {
+                metadata_buffer.extend_from_slice(&data);
+                if let Some(result) = process_buffer(&metadata_buffer)? {
+                    return Ok(result);
+                }
+                if metadata_buffer.len() > max_prelude_size {
+                    return Err(GatewayError::InvalidResponse(format!(
+                        "Metadata prelude exceeds {} bytes",
+                        max_prelude_size
+                    )));
+                }
             }
-        } else {
-            null_count = 0;
+            Some(StreamEvent::Complete(Some(error))) => return Err(GatewayError::FunctionError(error)),
+            Some(StreamEvent::Complete(None)) | None => break,
         }
     }
-    (None, Vec::new())
+    Err(GatewayError::InvalidResponse(
+        "Response stream ended before the end of the metadata prelude".to_string(),
+    ))
+}
+
+/// Splits a buffer at the eight NUL bytes ending the metadata prelude. Returns `None` while the
+/// delimiter has not been received.
+fn process_buffer(buffer: &[u8]) -> Result<Option<(MetadataPrelude, Vec<u8>)>, GatewayError> {
+    let Some(end) = buffer
+        .windows(PRELUDE_DELIMITER.len())
+        .position(|w| w == PRELUDE_DELIMITER)
+    else {
+        return Ok(None);
+    };
+    let metadata_prelude: MetadataPrelude = serde_json::from_slice(&buffer[..end])
+        .map_err(|e| GatewayError::InvalidResponse(format!("Malformed metadata prelude: {}", e)))?;
+    tracing::debug!(metadata_prelude=?metadata_prelude);
+    // Save remaining data after metadata
+    let remaining_data = buffer[end + PRELUDE_DELIMITER.len()..].to_vec();
+    Ok(Some((metadata_prelude, remaining_data)))
 }
@@ -1,11 +1,12 @@
 use super::*;
-// use axum::http::StatusCode;
-// use aws_smithy_types::Blob;
-// use std::collections::HashMap;
-// use aws_sdk_lambda::types::InvokeWithResponseStreamResponseEvent;
-// use aws_sdk_lambda::operation::invoke_with_response_stream::InvokeWithResponseStreamOutput;
-// use aws_sdk_lambda::primitives::event_stream::EventReceiver;
-// use aws_sdk_lambda::operation::invoke_with_response_stream::InvokeWithResponseStreamError;
+use crate::invoker::MockInvoker;
+
+fn target(payload_format: PayloadFormat) -> LambdaTarget {
+    LambdaTarget {
+        payload_format,
+        ..LambdaTarget::for_test("function")
+    }
+}
 
 #[tokio::test]
 async fn test_health() {
@@ -13,38 +14,24 @@ async fn test_health() {
     assert_eq!(response.status(), StatusCode::OK);
 }
 
-#[tokio::test]
-async fn test_to_string_map() {
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse().unwrap());
-    headers.insert("X-Custom-Header", "test-value".parse().unwrap());
-
-    let result = to_string_map(&headers);
-
-    assert_eq!(result.len(), 2);
-    assert_eq!(result.get("content-type"), Some(&"application/json".to_string()));
-    assert_eq!(result.get("x-custom-header"), Some(&"test-value".to_string()));
-}
-
 #[tokio::test]
 async fn test_handle_buffered_response() {
     let lambda_response = LambdaResponse {
-        status_code: 200,
+        status_code: Some(200),
         status_description: Some("OK".to_string()),
         is_base64_encoded: Some(false),
         headers: Some(HashMap::from([
             ("Content-Type".to_string(), "text/plain".to_string()),
         ])),
-        body: "Hello, World!".to_string(),
+        multi_value_headers: None,
+        cookies: None,
+        body: Some("Hello, World!".to_string()),
     };
 
     let payload = serde_json::to_vec(&lambda_response).unwrap();
-    let invoke_output = aws_sdk_lambda::operation::invoke::InvokeOutput::builder()
-        .payload(Blob::new(payload))
-        .status_code(200)
-        .build();
+    let invoke_output = InvokeResponse::new(payload);
 
-    let response = handle_buffered_response(invoke_output).await;
+    let response = handle_buffered_response(invoke_output, &target(PayloadFormat::Alb)).await.unwrap();
 
     assert_eq!(response.status(), StatusCode::OK);
     assert_eq!(
@@ -55,84 +42,767 @@ async fn test_handle_buffered_response() {
     assert_eq!(body, "Hello, World!");
 }
 
-// #[tokio::test]
-// async fn test_detect_metadata() {
-//     let payload = r#"{"statusCode": 200, "headers": {"Content-Type": "text/plain"}, "body": "Hello"}"#;
-//     let full_payload = payload.as_bytes().to_vec();
-//     let chunk = InvokeWithResponseStreamResponseEvent::PayloadChunk(
-//         aws_sdk_lambda::types::InvokeResponseStreamUpdate::builder()
-//             .payload(Blob::new(full_payload.clone()))
-//             .build(),
-//     );
-
-//     let event_receiver = EventReceiver {
-//         inner: vec![chunk],
-//     };
-
-//     let mut resp = InvokeWithResponseStreamOutput::builder()
-//         .event_stream(event_receiver)
-//         .build()
-//         .unwrap();
-
-//     // Type annotation to help the compiler
-//     let resp: InvokeWithResponseStreamOutput = resp;
-
-//     let (has_metadata, first_chunk) = detect_metadata(&mut resp).await;
-
-//     assert!(has_metadata);
-//     assert_eq!(first_chunk.unwrap(), full_payload);
-// }
-
-// #[tokio::test]
-// async fn test_collect_metadata() {
-//     let payload = r#"{"statusCode": 200, "headers": {"Content-Type": "text/plain"}, "body": "Hello"}"#;
-//     let null_padding = vec![0u8; 8];
-//     let remaining_data = b"Remaining data";
-
-//     let mut full_payload = payload.as_bytes().to_vec();
-//     full_payload.extend_from_slice(&null_padding);
-//     full_payload.extend_from_slice(remaining_data);
-
-//     let chunk = InvokeWithResponseStreamResponseEvent::PayloadChunk(
-//         aws_sdk_lambda::types::InvokeResponseStreamUpdate::builder()
-//             .payload(Blob::new(full_payload))
-//             .build(),
-//     );
-
-//     let event_receiver =EventReceiver {
-//         inner: vec![chunk],
-//     };
-
-//     let mut resp = InvokeWithResponseStreamOutput::builder()
-//         .event_stream(event_receiver)
-//         .build()
-//         .unwrap();
-
-//     let mut metadata_buffer = Vec::new();
-//     let (metadata_prelude, remaining) = collect_metadata(&mut resp, &mut metadata_buffer).await;
-
-//     assert!(metadata_prelude.is_some());
-//     let prelude = metadata_prelude.unwrap();
-//     assert_eq!(prelude.status_code, StatusCode::OK);
-//     assert_eq!(prelude.headers.get("content-type").unwrap(), "text/plain");
-//     assert_eq!(remaining, remaining_data);
-// }
-
-// #[tokio::test]
-// async fn test_process_buffer() {
-//     let payload = r#"{"statusCode": 200, "headers": {"Content-Type": "text/plain"}, "body": "Hello"}"#;
-//     let null_padding = vec![0u8; 8];
-//     let remaining_data = b"Remaining data";
-//
-//     let mut buffer = payload.as_bytes().to_vec();
-//     buffer.extend_from_slice(&null_padding);
-//     buffer.extend_from_slice(remaining_data);
-//
-//     let (metadata_prelude, remaining) = process_buffer(&buffer);
-//
-//     assert!(metadata_prelude.is_some());
-//     let prelude = metadata_prelude.unwrap();
-//     assert_eq!(prelude.status_code, StatusCode::OK);
-//     assert_eq!(prelude.headers.get("content-type").unwrap(), "text/plain");
-//     assert_eq!(remaining, remaining_data);
-// }
+#[tokio::test]
+async fn test_handle_buffered_response_v2() {
+    let payload = r#"{"statusCode": 201, "cookies": ["a=1", "b=2"], "body": "created"}"#;
+    let invoke_output = InvokeResponse::new(payload);
+
+    let response = handle_buffered_response(invoke_output, &target(PayloadFormat::ApiGatewayV2)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let cookies: Vec<_> = response.headers().get_all("set-cookie").iter().collect();
+    assert_eq!(cookies, vec!["a=1", "b=2"]);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "created");
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_v2_without_status_code() {
+    let payload = r#"{"message": "hello"}"#;
+    let invoke_output = InvokeResponse::new(payload);
+
+    let response = handle_buffered_response(invoke_output, &target(PayloadFormat::ApiGatewayV2)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_missing_status_code() {
+    let invoke_output = InvokeResponse::new(r#"{"body": "hello"}"#);
+
+    let error = handle_buffered_response(invoke_output, &target(PayloadFormat::Alb))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, GatewayError::InvalidResponse(_)));
+    assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_multi_value_headers() {
+    let payload = r#"{
+        "statusCode": 200,
+        "multiValueHeaders": {"set-cookie": ["a=1", "b=2"], "content-type": ["text/plain"]},
+        "body": "ok"
+    }"#;
+    let invoke_output = InvokeResponse::new(payload);
+
+    let response = handle_buffered_response(invoke_output, &target(PayloadFormat::Alb)).await.unwrap();
+
+    let cookies: Vec<_> = response.headers().get_all("set-cookie").iter().collect();
+    assert_eq!(cookies, vec!["a=1", "b=2"]);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/plain");
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_raw() {
+    let payload = r#"{"result": 42}"#;
+    let invoke_output = InvokeResponse::new(payload);
+
+    let response = handle_buffered_response(invoke_output, &target(PayloadFormat::Raw)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/json");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_raw_function_error() {
+    let payload = r#"{"errorMessage": "boom", "errorType": "Error"}"#;
+    let invoke_output = InvokeResponse::function_error("Unhandled", payload);
+    let mut target = target(PayloadFormat::Raw);
+    target.raw_content_type = "application/vnd.example+json".to_string();
+
+    let response = handle_buffered_response(invoke_output, &target).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(response.headers().get("content-type").unwrap(), "application/vnd.example+json");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_template() {
+    let payload = r#"{"items": [1, 2]}"#;
+    let invoke_output = InvokeResponse::new(payload);
+    let mut target = target(PayloadFormat::Template);
+    let response_template = r#"{"statusCode": {% if function_error %}500{% else %}200{% endif %}, "headers": {"x-count": "{{ json["items"]|length }}"}, "body": {{ body|tojson }}}"#;
+    target.templates = Some(Arc::new(template::Templates::new(Some("{{ body }}"), Some(response_template)).unwrap()));
+
+    let response = handle_buffered_response(invoke_output, &target).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("x-count").unwrap(), "2");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_function_error() {
+    let invoke_output = InvokeResponse::function_error("Unhandled", r#"{"errorMessage": "boom", "errorType": "RuntimeError"}"#);
+
+    let error = handle_buffered_response(invoke_output, &target(PayloadFormat::Alb))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, GatewayError::FunctionError(_)));
+    assert!(error.message().contains("boom"));
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_function_timeout() {
+    let invoke_output = InvokeResponse::function_error("Unhandled", r#"{"errorMessage": "Task timed out after 3.00 seconds", "errorType": "Sandbox.Timedout"}"#);
+
+    let error = handle_buffered_response(invoke_output, &target(PayloadFormat::ApiGatewayV2))
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.status_code(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[tokio::test]
+async fn test_handle_buffered_response_invalid_base64() {
+    let invoke_output = InvokeResponse::new(r#"{"statusCode": 200, "isBase64Encoded": true, "body": "not base64!"}"#);
+
+    let error = handle_buffered_response(invoke_output, &target(PayloadFormat::Alb))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(error, GatewayError::InvalidResponse(_)));
+}
+
+#[test]
+fn test_accepts_trailers() {
+    let mut headers = HeaderMap::new();
+    assert!(!accepts_trailers(&headers));
+
+    headers.insert("te", HeaderValue::from_static("gzip, Trailers"));
+    assert!(accepts_trailers(&headers));
+}
+
+#[test]
+fn test_error_trailers() {
+    let error = GatewayError::FunctionError(FunctionError {
+        error_type: "Runtime.ExitError".to_string(),
+        ..Default::default()
+    });
+    assert_eq!(error_trailers(&error).get(ERROR_CODE_TRAILER).unwrap(), "Runtime.ExitError");
+
+    let error = GatewayError::InvokeFailed("connection reset".to_string());
+    assert_eq!(error_trailers(&error).get(ERROR_CODE_TRAILER).unwrap(), "InvokeFailed");
+}
+
+#[test]
+fn test_process_buffer_prelude() {
+    let buffer = b"{\"statusCode\": 201, \"headers\": {\"x-custom\": \"1\"}, \"cookies\": [\"a=b\"]}\0\0\0\0\0\0\0\0hello";
+
+    let (prelude, remaining) = process_buffer(buffer).unwrap().unwrap();
+
+    assert_eq!(prelude.status_code, StatusCode::CREATED);
+    assert_eq!(prelude.headers.get("x-custom").unwrap(), "1");
+    assert_eq!(prelude.cookies, vec!["a=b"]);
+    assert_eq!(remaining, b"hello");
+}
+
+#[test]
+fn test_process_buffer_prelude_without_headers() {
+    let (prelude, remaining) = process_buffer(b"{\"statusCode\": 204}\0\0\0\0\0\0\0\0").unwrap().unwrap();
+
+    assert_eq!(prelude.status_code, StatusCode::NO_CONTENT);
+    assert!(prelude.headers.is_empty());
+    assert!(prelude.cookies.is_empty());
+    assert!(remaining.is_empty());
+}
+
+#[test]
+fn test_process_buffer_incomplete_prelude() {
+    assert!(process_buffer(b"{\"statusCode\": 200}\0\0\0").unwrap().is_none());
+}
+
+#[test]
+fn test_process_buffer_malformed_prelude() {
+    let error = process_buffer(b"not json\0\0\0\0\0\0\0\0").unwrap_err();
+    assert!(matches!(error, GatewayError::InvalidResponse(_)));
+}
+
+#[tokio::test]
+async fn test_stream_limits_idle_timeout() {
+    let limits = StreamLimits {
+        idle_timeout: Some(Duration::from_millis(10)),
+        max_duration: None,
+        deadline: None,
+    };
+
+    assert_eq!(limits.apply(async { 1 }).await.unwrap(), 1);
+    let error = limits.apply(std::future::pending::<()>()).await.unwrap_err();
+    assert!(matches!(error, GatewayError::Timeout(ref m) if m.contains("stream_idle_timeout")));
+}
+
+#[tokio::test]
+async fn test_stream_limits_max_duration() {
+    let limits = StreamLimits {
+        idle_timeout: Some(Duration::from_secs(60)),
+        max_duration: Some(Duration::from_millis(10)),
+        deadline: Some(Instant::now() + Duration::from_millis(10)),
+    };
+
+    let error = limits.apply(std::future::pending::<()>()).await.unwrap_err();
+    assert!(matches!(error, GatewayError::Timeout(ref m) if m.contains("stream_max_duration")));
+}
+
+fn app_config(lambda_invoke_mode: LambdaInvokeMode) -> Config {
+    Config {
+        lambda_function_name: "function".to_string(),
+        lambda_invoke_mode,
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..Default::default()
+    }
+}
+
+async fn send(config: Config, invoker: Arc<MockInvoker>, request: axum::http::Request<Body>) -> Response {
+    use tower::ServiceExt;
+
+    let state = ApplicationState::new(config, invoker).unwrap();
+    app(state).oneshot(request).await.unwrap()
+}
+
+fn post(uri: &str, body: &'static str) -> axum::http::Request<Body> {
+    axum::http::Request::post(uri)
+        .header("host", "api.example.com")
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_handler_buffered() {
+    let invoker = Arc::new(MockInvoker::new().with_response(
+        "function",
+        InvokeResponse::new(r#"{"statusCode": 201, "headers": {"x-id": "42"}, "body": "created"}"#),
+    ));
+
+    let response = send(
+        app_config(LambdaInvokeMode::Buffered),
+        invoker.clone(),
+        post("/items?a=1", r#"{"name": "item"}"#),
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers().get("x-id").unwrap(), "42");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "created");
+
+    let invocations = invoker.invocations();
+    assert_eq!(invocations.len(), 1);
+    assert_eq!(invocations[0].function_name, "function");
+    let event: serde_json::Value = serde_json::from_slice(&invocations[0].payload).unwrap();
+    assert_eq!(event["rawPath"], "/items");
+    assert_eq!(event["queryStringParameters"]["a"], "1");
+    assert_eq!(event["body"], r#"{"name": "item"}"#);
+}
+
+#[tokio::test]
+async fn test_metrics_not_shadowing_routes() {
+    use tower::ServiceExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_response(
+        "function",
+        InvokeResponse::new(r#"{"statusCode": 200, "body": "from the function"}"#),
+    ));
+    let state = ApplicationState::new(app_config(LambdaInvokeMode::Buffered), invoker).unwrap();
+    state.metrics.record_stream_client_disconnect();
+
+    let request = || axum::http::Request::get("/metrics").body(Body::empty()).unwrap();
+    let response = app(state.clone()).oneshot(request()).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "from the function");
+
+    let response = metrics_app(state).oneshot(request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(String::from_utf8_lossy(&body).contains("gateway_stream_client_disconnects_total 1"));
+}
+
+#[tokio::test]
+async fn test_handler_invoke_error() {
+    let invoker = Arc::new(MockInvoker::new().with_error("function", GatewayError::Throttled("Rate exceeded".to_string())));
+
+    let response = send(app_config(LambdaInvokeMode::Buffered), invoker, post("/", "")).await;
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("x-request-id"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["errorType"], "Throttled");
+}
+
+#[tokio::test]
+async fn test_handler_stream_with_prelude() {
+    let prelude = b"{\"statusCode\": 201, \"headers\": {\"content-type\": \"text/plain\"}, \"cookies\": []}\0\0\0\0\0\0\0\0hel";
+    let invoker = Arc::new(MockInvoker::new().with_stream(
+        "function",
+        Some(HTTP_INTEGRATION_RESPONSE_CONTENT_TYPE),
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(prelude))),
+            Ok(StreamEvent::Data(Bytes::from_static(b"lo"))),
+            Ok(StreamEvent::Complete(None)),
+        ],
+    ));
+
+    let response = send(app_config(LambdaInvokeMode::ResponseStream), invoker, post("/", "")).await;
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/plain");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "hello");
+}
+
+#[tokio::test]
+async fn test_handler_stream_function_error_before_data() {
+    let error = FunctionError {
+        error_type: "Runtime.ExitError".to_string(),
+        error_message: "exited".to_string(),
+        ..Default::default()
+    };
+    let invoker = Arc::new(MockInvoker::new().with_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Complete(Some(error)))],
+    ));
+
+    let response = send(app_config(LambdaInvokeMode::ResponseStream), invoker, post("/", "")).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+async fn test_handler_stream_error_aborts_body() {
+    use http_body_util::BodyExt;
+
+    let error = FunctionError {
+        error_type: "Runtime.ExitError".to_string(),
+        ..Default::default()
+    };
+    let invoker = Arc::new(MockInvoker::new().with_stream(
+        "function",
+        None,
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(b"partial"))),
+            Ok(StreamEvent::Complete(Some(error))),
+        ],
+    ));
+
+    let response = send(app_config(LambdaInvokeMode::ResponseStream), invoker, post("/", "")).await;
+
+    // Without `TE: trailers`, the error can only show as a body that fails to complete.
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key("trailer"));
+    let mut body = response.into_body();
+    assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "partial");
+    assert!(body.frame().await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_handler_stream_error_trailers() {
+    use http_body_util::BodyExt;
+
+    let error = FunctionError {
+        error_type: "Runtime.ExitError".to_string(),
+        ..Default::default()
+    };
+    let invoker = Arc::new(MockInvoker::new().with_stream(
+        "function",
+        None,
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(b"partial"))),
+            Ok(StreamEvent::Complete(Some(error))),
+        ],
+    ));
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.stream_error_trailers = true;
+    let mut request = post("/", "");
+    request.headers_mut().insert("te", HeaderValue::from_static("trailers"));
+
+    let response = send(config, invoker, request).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("trailer").unwrap(), ERROR_CODE_TRAILER);
+    let collected = response.into_body().collect().await.unwrap();
+    assert_eq!(collected.trailers().unwrap().get(ERROR_CODE_TRAILER).unwrap(), "Runtime.ExitError");
+    assert_eq!(collected.to_bytes(), "partial");
+}
+
+#[tokio::test]
+async fn test_handler_stream_client_disconnect() {
+    use tower::ServiceExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"partial")))],
+    ));
+    let state = ApplicationState::new(app_config(LambdaInvokeMode::ResponseStream), invoker.clone()).unwrap();
+
+    let response = app(state.clone()).oneshot(post("/", "")).await.unwrap();
+    let mut body = response.into_body();
+    assert_eq!(next_data(&mut body).await, "partial");
+    assert_eq!(invoker.open_streams(), 1);
+    drop(body);
+
+    // Dropping the body stops the forwarding task, which drops the event stream.
+    tokio::time::timeout(Duration::from_secs(3), async {
+        while invoker.open_streams() > 0 || state.metrics.stream_client_disconnects() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(state.metrics.stream_client_disconnects(), 1);
+}
+
+#[tokio::test]
+async fn test_handler_stream_idle_timeout() {
+    use http_body_util::BodyExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"partial")))],
+    ));
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.stream_idle_timeout = Some(1);
+
+    let response = send(config, invoker, post("/", "")).await;
+
+    // The headers were sent with the first chunk; the timeout ends the body without an error.
+    assert_eq!(response.status(), StatusCode::OK);
+    let collected = response.into_body().collect().await.unwrap();
+    assert!(collected.trailers().is_none());
+    assert_eq!(collected.to_bytes(), "partial");
+}
+
+#[tokio::test]
+async fn test_handler_stream_idle_timeout_trailers() {
+    use http_body_util::BodyExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"partial")))],
+    ));
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.stream_idle_timeout = Some(1);
+    config.stream_error_trailers = true;
+    let mut request = post("/", "");
+    request.headers_mut().insert("te", HeaderValue::from_static("trailers"));
+
+    let response = send(config, invoker, request).await;
+
+    let collected = response.into_body().collect().await.unwrap();
+    assert_eq!(collected.trailers().unwrap().get(ERROR_CODE_TRAILER).unwrap(), "Timeout");
+    assert_eq!(collected.to_bytes(), "partial");
+}
+
+#[tokio::test]
+async fn test_handler_sse() {
+    let invoker = Arc::new(MockInvoker::new().with_stream(
+        "function",
+        None,
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(b"data: one\n\ndata: t"))),
+            Ok(StreamEvent::Data(Bytes::from_static(b"wo\n\n"))),
+            Ok(StreamEvent::Complete(None)),
+        ],
+    ));
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.sse = true;
+
+    let response = send(config, invoker, post("/", "")).await;
+
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/event-stream");
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-cache");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "data: one\n\ndata: two\n\n");
+}
+
+/// The next data frame of `body`, failing the test if none arrives within three seconds.
+async fn next_data(body: &mut Body) -> Bytes {
+    use http_body_util::BodyExt;
+
+    let frame = tokio::time::timeout(Duration::from_secs(3), body.frame()).await.unwrap();
+    frame.unwrap().unwrap().into_data().unwrap()
+}
+
+fn sse_config() -> Config {
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.sse = true;
+    config.sse_keepalive_interval = 1;
+    config
+}
+
+#[tokio::test]
+async fn test_handler_sse_keepalive_before_first_event() {
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream("function", None, Vec::new()));
+
+    let response = tokio::time::timeout(Duration::from_secs(3), send(sse_config(), invoker, post("/", "")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/event-stream");
+    let mut body = response.into_body();
+    assert_eq!(next_data(&mut body).await, sse::KEEPALIVE);
+}
+
+#[tokio::test]
+async fn test_handler_sse_keepalive_after_event() {
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"data: one\n\n")))],
+    ));
+
+    let response = send(sse_config(), invoker, post("/", "")).await;
+
+    let mut body = response.into_body();
+    assert_eq!(next_data(&mut body).await, "data: one\n\n");
+    assert_eq!(next_data(&mut body).await, sse::KEEPALIVE);
+}
+
+#[tokio::test]
+async fn test_handler_sse_no_keepalive_inside_event() {
+    use http_body_util::BodyExt;
+
+    // Larger than the pending data the event buffer holds back, and without an event boundary.
+    let oversized = Bytes::from(vec![b'x'; 1024 * 1024 + 1]);
+    let invoker = Arc::new(MockInvoker::new().with_stalled_stream(
+        "function",
+        None,
+        vec![Ok(StreamEvent::Data(oversized.clone()))],
+    ));
+
+    let response = send(sse_config(), invoker, post("/", "")).await;
+
+    let mut body = response.into_body();
+    assert_eq!(next_data(&mut body).await, oversized);
+    assert!(tokio::time::timeout(Duration::from_millis(2500), body.frame()).await.is_err());
+}
+
+#[tokio::test]
+async fn test_handler_sse_last_event_id() {
+    let stream = || vec![Ok(StreamEvent::Complete(None))];
+    let invoker = Arc::new(
+        MockInvoker::new()
+            .with_stream("function", None, stream())
+            .with_stream("template-function", None, stream()),
+    );
+    let mut config = app_config(LambdaInvokeMode::ResponseStream);
+    config.sse = true;
+    let mut route: crate::config::RouteConfig =
+        serde_yaml::from_str("{path: /template, lambda_function_name: template-function}").unwrap();
+    route.payload_format = Some(PayloadFormat::Template);
+    route.request_template = Some(r#"{"resume_from": {{ last_event_id|tojson }}}"#.to_string());
+    config.routes = vec![route];
+
+    for path in ["/events", "/template"] {
+        let mut request = post(path, "");
+        request.headers_mut().insert("last-event-id", HeaderValue::from_static("42"));
+        let response = send(config.clone(), invoker.clone(), request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let invocations = invoker.invocations();
+    let event: serde_json::Value = serde_json::from_slice(&invocations[0].payload).unwrap();
+    assert_eq!(event["headers"]["last-event-id"], "42");
+    let event: serde_json::Value = serde_json::from_slice(&invocations[1].payload).unwrap();
+    assert_eq!(event, serde_json::json!({"resume_from": "42"}));
+}
+
+#[tokio::test]
+async fn test_handler_canary() {
+    use crate::config::{RouteConfig, StickyConfig, VariantConfig};
+    use tower::ServiceExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_response(
+        "orders",
+        InvokeResponse::new(r#"{"statusCode": 200, "body": "ok"}"#),
+    ));
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: orders}").unwrap();
+    route.variants = vec![
+        VariantConfig {
+            qualifier: "1".to_string(),
+            weight: 50,
+        },
+        VariantConfig {
+            qualifier: "2".to_string(),
+            weight: 50,
+        },
+    ];
+    route.sticky = Some(StickyConfig {
+        header: Some("x-user-id".to_string()),
+        cookie: None,
+    });
+    let config = Config {
+        routes: vec![route],
+        ..app_config(LambdaInvokeMode::Buffered)
+    };
+    let state = ApplicationState::new(config, invoker.clone()).unwrap();
+
+    let mut variants = Vec::new();
+    for _ in 0..3 {
+        let mut request = post("/orders", "{}");
+        request.headers_mut().insert("x-user-id", "alice".parse().unwrap());
+        let response = app(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        variants.push(response.headers()[VARIANT_HEADER].to_str().unwrap().to_string());
+    }
+
+    assert!(variants.iter().all(|v| v == &variants[0]));
+    let qualifiers: Vec<_> = invoker.invocations().into_iter().map(|i| i.qualifier).collect();
+    assert_eq!(qualifiers, vec![Some(variants[0].clone()); 3]);
+    assert_eq!(state.metrics.variant_responses("orders", &variants[0], "2xx"), 3);
+
+    let response = app(state.clone()).oneshot(post("/other", "{}")).await.unwrap();
+    assert!(!response.headers().contains_key(VARIANT_HEADER));
+}
+
+#[tokio::test]
+async fn test_handler_mirror() {
+    use crate::config::{MirrorConfig, RouteConfig};
+    use tower::ServiceExt;
+
+    let invoker = Arc::new(MockInvoker::new().with_response(
+        "orders",
+        InvokeResponse::new(r#"{"statusCode": 200, "body": "ok"}"#),
+    ));
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: orders}").unwrap();
+    route.mirror = Some(serde_yaml::from_str::<MirrorConfig>("{lambda_function_name: orders-v2}").unwrap());
+    let config = Config {
+        routes: vec![route],
+        ..app_config(LambdaInvokeMode::Buffered)
+    };
+    let state = ApplicationState::new(config, invoker.clone()).unwrap();
+
+    // The mirror function does not exist; the response is not affected.
+    let response = app(state).oneshot(post("/orders", r#"{"a": 1}"#)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "ok");
+
+    let mirrored = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(invocation) = invoker.invocations().into_iter().find(|i| i.function_name == "orders-v2") {
+                return invocation;
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(mirrored.payload, invoker.invocations()[0].payload);
+}
+
+fn fallback_config(fallback: &str) -> Config {
+    use crate::config::RouteConfig;
+
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: orders}").unwrap();
+    route.fallback = Some(serde_yaml::from_str(fallback).unwrap());
+    Config {
+        routes: vec![route],
+        ..app_config(LambdaInvokeMode::Buffered)
+    }
+}
+
+#[tokio::test]
+async fn test_handler_fallback_function() {
+    let invoker = Arc::new(
+        MockInvoker::new()
+            .with_response(
+                "orders",
+                InvokeResponse::function_error("Unhandled", r#"{"errorType": "Error", "errorMessage": "boom"}"#),
+            )
+            .with_response(
+                "orders-fallback",
+                InvokeResponse::new(r#"{"statusCode": 200, "body": "cached"}"#),
+            ),
+    );
+    let config = fallback_config("{lambda_function_name: orders-fallback}");
+
+    let response = send(config, invoker.clone(), post("/orders", "{}")).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[FALLBACK_HEADER], "FunctionError");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, "cached");
+    let invocations = invoker.invocations();
+    assert_eq!(invocations[1].function_name, "orders-fallback");
+    assert_eq!(invocations[1].payload, invocations[0].payload);
+}
+
+#[tokio::test]
+async fn test_handler_fallback_raw() {
+    let invoker = Arc::new(
+        MockInvoker::new()
+            .with_response(
+                "orders",
+                InvokeResponse::function_error("Unhandled", r#"{"errorType": "Error", "errorMessage": "boom"}"#),
+            )
+            .with_response("orders-fallback", InvokeResponse::new(r#"{"orders": []}"#)),
+    );
+    let mut config = fallback_config("{lambda_function_name: orders-fallback, errors: [FunctionError]}");
+    config.routes[0].payload_format = Some(PayloadFormat::Raw);
+
+    let response = send(config, invoker, post("/orders", "{}")).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[FALLBACK_HEADER], "FunctionError");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, r#"{"orders": []}"#);
+}
+
+#[tokio::test]
+async fn test_handler_fallback_response() {
+    let invoker = Arc::new(MockInvoker::new().with_error("orders", GatewayError::Throttled("slow down".to_string())));
+    let config = fallback_config(r#"{response: {status_code: 503, body: '{"message": "busy"}'}}"#);
+
+    let response = send(config, invoker, post("/orders", "{}")).await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[FALLBACK_HEADER], "Throttled");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, r#"{"message": "busy"}"#);
+}
+
+#[tokio::test]
+async fn test_handler_fallback_not_triggered() {
+    let invoker = Arc::new(MockInvoker::new().with_response(
+        "orders-fallback",
+        InvokeResponse::new(r#"{"statusCode": 200, "body": "cached"}"#),
+    ));
+    let config = fallback_config("{lambda_function_name: orders-fallback, errors: [Timeout]}");
+
+    // The primary function does not exist, which is not a Timeout.
+    let response = send(config, invoker.clone(), post("/orders", "{}")).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert!(!response.headers().contains_key(FALLBACK_HEADER));
+    assert_eq!(invoker.invocations().len(), 1);
+}
+
+#[tokio::test]
+async fn test_handler_fallback_failure() {
+    let invoker = Arc::new(MockInvoker::new().with_error("orders", GatewayError::Timeout("timed out".to_string())));
+    let config = fallback_config("{lambda_function_name: orders-fallback}");
+
+    let response = send(config, invoker.clone(), post("/orders", "{}")).await;
+
+    // The missing fallback function is logged; the client sees the primary error.
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert!(!response.headers().contains_key(FALLBACK_HEADER));
+    assert_eq!(invoker.invocations().len(), 2);
+}
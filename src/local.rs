@@ -0,0 +1,504 @@
+use crate::config::LocalRuntimeConfig;
+use crate::error::{FunctionError, GatewayError, TIMEOUT_ERROR_TYPE};
+use crate::invoker::{InvokeResponse, Invoker, ResponseStream, StreamEvent};
+use crate::router::LambdaTarget;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use futures::future::BoxFuture;
+use futures::stream;
+use futures::{FutureExt, StreamExt};
+use http_body_util::BodyExt;
+use serde_json::json;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+
+const RESPONSE_MODE_HEADER: &str = "lambda-runtime-function-response-mode";
+const ERROR_TYPE_HEADER: &str = "lambda-runtime-function-error-type";
+/// Trailer carrying the base64 encoded error of a streamed response that failed.
+const ERROR_BODY_TRAILER: &str = "lambda-runtime-function-error-body";
+
+/// Processes that exit sooner than this after starting are restarted only after this delay.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// Chunks of a streamed response held between the runtime and the gateway.
+const STREAM_CHANNEL_CAPACITY: usize = 16;
+
+/// Invokes functions in local processes of a handler executable, which fetch invocations from
+/// the gateway through the Lambda Runtime API. Each process gets its own Runtime API endpoint and
+/// handles one invocation at a time.
+pub struct LocalInvoker {
+    invocations: mpsc::UnboundedSender<Invocation>,
+}
+
+/// An invocation waiting for a process.
+struct Invocation {
+    request_id: String,
+    function_arn: String,
+    payload: Vec<u8>,
+    result: oneshot::Sender<Outcome>,
+}
+
+/// How the function answered an invocation.
+enum Outcome {
+    Response { content_type: Option<String>, body: Bytes },
+    Stream(ResponseStream),
+    Error(FunctionError),
+}
+
+impl LocalInvoker {
+    /// Starts `concurrency` processes of the configured executable. Fails when the executable
+    /// cannot be started; later failures are logged and the process restarted.
+    pub async fn start(config: &LocalRuntimeConfig, function_name: &str) -> std::io::Result<Self> {
+        let (invocations, receiver) = mpsc::unbounded_channel();
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let timeout = Duration::from_secs(config.timeout);
+
+        for _ in 0..config.concurrency.max(1) {
+            let worker = Arc::new(Worker::new(receiver.clone(), timeout));
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let process = Process {
+                config: config.clone(),
+                function_name: function_name.to_string(),
+                runtime_api: listener.local_addr()?.to_string(),
+            };
+            let child = process.spawn()?;
+
+            let router = runtime_api(worker.clone());
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(listener, router).await {
+                    tracing::error!(error = %e, "Local Runtime API server failed");
+                }
+            });
+            tokio::spawn(supervise(worker, process, child));
+        }
+        Ok(LocalInvoker { invocations })
+    }
+
+    async fn dispatch(&self, target: &LambdaTarget, payload: Vec<u8>) -> Result<Outcome, GatewayError> {
+        self.enqueue(target, payload)?
+            .await
+            .map_err(|_| GatewayError::InvokeFailed("The local runtime dropped the invocation".to_string()))
+    }
+
+    /// Queues an invocation for the next free process.
+    fn enqueue(&self, target: &LambdaTarget, payload: Vec<u8>) -> Result<oneshot::Receiver<Outcome>, GatewayError> {
+        let (result, outcome) = oneshot::channel();
+        let invocation = Invocation {
+            request_id: uuid::Uuid::new_v4().to_string(),
+            function_arn: function_arn(target),
+            payload,
+            result,
+        };
+        self.invocations
+            .send(invocation)
+            .map_err(|_| GatewayError::InvokeFailed("The local runtime is not running".to_string()))?;
+        Ok(outcome)
+    }
+}
+
+impl Invoker for LocalInvoker {
+    fn invoke<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<InvokeResponse, GatewayError>> {
+        async move {
+            match self.dispatch(target, payload).await? {
+                Outcome::Response { body, .. } => Ok(InvokeResponse::new(body)),
+                Outcome::Error(error) => Ok(unhandled(&error)),
+                // Lambda buffers the responses of streaming functions invoked without streaming.
+                Outcome::Stream(mut stream) => {
+                    let mut body = Vec::new();
+                    while let Some(event) = stream.events.next().await {
+                        match event? {
+                            StreamEvent::Data(data) => body.extend_from_slice(&data),
+                            StreamEvent::Complete(Some(error)) => return Ok(unhandled(&error)),
+                            StreamEvent::Complete(None) => break,
+                        }
+                    }
+                    Ok(InvokeResponse::new(body))
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn invoke_stream<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<ResponseStream, GatewayError>> {
+        async move {
+            let (content_type, events) = match self.dispatch(target, payload).await? {
+                Outcome::Stream(stream) => return Ok(stream),
+                Outcome::Response { content_type, body } => (
+                    content_type,
+                    vec![Ok(StreamEvent::Data(body)), Ok(StreamEvent::Complete(None))],
+                ),
+                Outcome::Error(error) => (None, vec![Ok(StreamEvent::Complete(Some(error)))]),
+            };
+            Ok(ResponseStream {
+                content_type,
+                events: stream::iter(events).boxed(),
+            })
+        }
+        .boxed()
+    }
+
+    fn invoke_event<'a>(
+        &'a self,
+        target: &'a LambdaTarget,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), GatewayError>> {
+        let result = self.enqueue(target, payload).map(|_| ());
+        futures::future::ready(result).boxed()
+    }
+}
+
+/// The buffered invocation result of a function error, as Lambda reports it.
+fn unhandled(error: &FunctionError) -> InvokeResponse {
+    let payload = serde_json::to_vec(error).unwrap_or_default();
+    InvokeResponse::function_error("Unhandled", payload)
+}
+
+fn function_arn(target: &LambdaTarget) -> String {
+    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let arn = format!(
+        "arn:aws:lambda:{}:000000000000:function:{}",
+        region, target.function_name
+    );
+    match &target.qualifier {
+        Some(qualifier) => format!("{}:{}", arn, qualifier),
+        None => arn,
+    }
+}
+
+/// The Runtime API endpoint of one process.
+struct Worker {
+    invocations: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Invocation>>>,
+    current: Mutex<Option<Current>>,
+    /// Wakes the supervisor when an invocation starts, so it can watch the deadline.
+    started: Notify,
+    timeout: Duration,
+}
+
+/// The invocation a process is handling.
+struct Current {
+    request_id: String,
+    deadline: Instant,
+    /// Taken when the process starts responding.
+    result: Option<oneshot::Sender<Outcome>>,
+}
+
+impl Worker {
+    fn new(invocations: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Invocation>>>, timeout: Duration) -> Self {
+        Worker {
+            invocations,
+            current: Mutex::new(None),
+            started: Notify::new(),
+            timeout,
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.current.lock().unwrap().as_ref().map(|c| c.deadline)
+    }
+
+    /// Takes the result channel of invocation `request_id`, once.
+    fn respond(&self, request_id: &str) -> Option<(oneshot::Sender<Outcome>, Instant)> {
+        let mut current = self.current.lock().unwrap();
+        let current = current.as_mut().filter(|c| c.request_id == request_id)?;
+        Some((current.result.take()?, current.deadline))
+    }
+
+    fn finish(&self, request_id: &str) {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|c| c.request_id == request_id) {
+            *current = None;
+        }
+    }
+
+    /// Ends the current invocation with `error` unless its response has started.
+    fn fail(&self, error: impl FnOnce(&str) -> FunctionError) {
+        if let Some(current) = self.current.lock().unwrap().take() {
+            if let Some(result) = current.result {
+                let _ = result.send(Outcome::Error(error(&current.request_id)));
+            }
+        }
+    }
+
+    /// Whether the current invocation has run past its deadline.
+    fn timed_out(&self) -> bool {
+        self.deadline().is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    fn timeout_error(&self, request_id: &str) -> FunctionError {
+        FunctionError {
+            error_type: TIMEOUT_ERROR_TYPE.to_string(),
+            error_message: format!(
+                "RequestId: {} Error: Task timed out after {}.00 seconds",
+                request_id,
+                self.timeout.as_secs()
+            ),
+            stack_trace: Vec::new(),
+        }
+    }
+}
+
+/// The Lambda Runtime API served to one process.
+fn runtime_api(worker: Arc<Worker>) -> Router {
+    Router::new()
+        .route("/2018-06-01/runtime/invocation/next", get(next_invocation))
+        .route(
+            "/2018-06-01/runtime/invocation/:request_id/response",
+            post(invocation_response),
+        )
+        .route(
+            "/2018-06-01/runtime/invocation/:request_id/error",
+            post(invocation_error),
+        )
+        .route("/2018-06-01/runtime/init/error", post(init_error))
+        .with_state(worker)
+}
+
+async fn next_invocation(State(worker): State<Arc<Worker>>) -> Response {
+    let Some(invocation) = worker.invocations.lock().await.recv().await else {
+        return StatusCode::GONE.into_response();
+    };
+
+    let deadline = Instant::now() + worker.timeout;
+    let deadline_ms = (SystemTime::now() + worker.timeout)
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    // A process asking for the next invocation has given up on the one it had.
+    *worker.current.lock().unwrap() = Some(Current {
+        request_id: invocation.request_id.clone(),
+        deadline,
+        result: Some(invocation.result),
+    });
+    worker.started.notify_one();
+
+    Response::builder()
+        .header("lambda-runtime-aws-request-id", invocation.request_id)
+        .header("lambda-runtime-deadline-ms", deadline_ms.to_string())
+        .header("lambda-runtime-invoked-function-arn", invocation.function_arn)
+        .header("content-type", "application/json")
+        .body(Body::from(invocation.payload))
+        .unwrap()
+}
+
+async fn invocation_response(
+    State(worker): State<Arc<Worker>>,
+    Path(request_id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let Some((result, deadline)) = worker.respond(&request_id) else {
+        return invalid_request_id(&request_id);
+    };
+    let content_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let streaming = headers
+        .get(RESPONSE_MODE_HEADER)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"streaming"));
+
+    if !streaming {
+        let outcome = match body.collect().await {
+            Ok(collected) => Outcome::Response {
+                content_type,
+                body: collected.to_bytes(),
+            },
+            Err(e) => Outcome::Error(exit_error(&request_id, &e.to_string())),
+        };
+        worker.finish(&request_id);
+        let _ = result.send(outcome);
+        return accepted();
+    }
+
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    let stream = ResponseStream {
+        content_type,
+        events: ReceiverStream::new(rx).boxed(),
+    };
+    if result.send(Outcome::Stream(stream)).is_ok() {
+        let error = forward_response_stream(body, &tx, &request_id, deadline, &worker).await;
+        let _ = tx.send(Ok(StreamEvent::Complete(error))).await;
+    }
+    worker.finish(&request_id);
+    accepted()
+}
+
+/// Passes a streamed response on until it ends, returning the function error it ended with.
+async fn forward_response_stream(
+    mut body: Body,
+    tx: &mpsc::Sender<Result<StreamEvent, GatewayError>>,
+    request_id: &str,
+    deadline: Instant,
+    worker: &Worker,
+) -> Option<FunctionError> {
+    loop {
+        let frame = tokio::select! {
+            frame = body.frame() => frame,
+            // The client went away, so the rest of the response is not needed.
+            _ = tx.closed() => return None,
+        };
+        match frame {
+            None => return None,
+            Some(Ok(frame)) => match frame.into_data() {
+                Ok(data) => {
+                    if tx.send(Ok(StreamEvent::Data(data))).await.is_err() {
+                        return None;
+                    }
+                }
+                Err(frame) => {
+                    let trailers = frame.into_trailers().unwrap_or_default();
+                    return trailers_error(&trailers);
+                }
+            },
+            // The supervisor kills processes that run past the deadline.
+            Some(Err(_)) if Instant::now() >= deadline => return Some(worker.timeout_error(request_id)),
+            Some(Err(e)) => return Some(exit_error(request_id, &e.to_string())),
+        }
+    }
+}
+
+/// The error reported in the trailers of a failed streamed response.
+fn trailers_error(trailers: &HeaderMap) -> Option<FunctionError> {
+    let error_type = trailers.get(ERROR_TYPE_HEADER)?.to_str().unwrap_or_default();
+    let body = trailers
+        .get(ERROR_BODY_TRAILER)
+        .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v.as_bytes()).ok())
+        .unwrap_or_default();
+    Some(FunctionError::parse(error_type, &body))
+}
+
+async fn invocation_error(
+    State(worker): State<Arc<Worker>>,
+    Path(request_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some((result, _)) = worker.respond(&request_id) else {
+        return invalid_request_id(&request_id);
+    };
+    let error_type = headers
+        .get(ERROR_TYPE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("Unhandled");
+    worker.finish(&request_id);
+    let _ = result.send(Outcome::Error(FunctionError::parse(error_type, &body)));
+    accepted()
+}
+
+async fn init_error(headers: HeaderMap, body: Bytes) -> Response {
+    let error_type = headers
+        .get(ERROR_TYPE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("Unknown");
+    let error = FunctionError::parse(error_type, &body);
+    tracing::error!(error = %error, "Local runtime failed to initialize");
+    accepted()
+}
+
+fn accepted() -> Response {
+    (StatusCode::ACCEPTED, Json(json!({"status": "OK"}))).into_response()
+}
+
+fn invalid_request_id(request_id: &str) -> Response {
+    let body = json!({
+        "errorMessage": format!("Invalid request ID: {}", request_id),
+        "errorType": "InvalidRequestID",
+    });
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+fn exit_error(request_id: &str, reason: &str) -> FunctionError {
+    FunctionError {
+        error_type: "Runtime.ExitError".to_string(),
+        error_message: format!("RequestId: {} Error: Runtime exited: {}", request_id, reason),
+        stack_trace: Vec::new(),
+    }
+}
+
+/// How to start a process of the handler executable.
+struct Process {
+    config: LocalRuntimeConfig,
+    function_name: String,
+    runtime_api: String,
+}
+
+impl Process {
+    fn spawn(&self) -> std::io::Result<Child> {
+        Command::new(&self.config.command)
+            .args(&self.config.args)
+            .env("AWS_LAMBDA_RUNTIME_API", &self.runtime_api)
+            .env("AWS_LAMBDA_FUNCTION_NAME", &self.function_name)
+            .env("AWS_LAMBDA_FUNCTION_VERSION", "$LATEST")
+            .env("AWS_LAMBDA_FUNCTION_MEMORY_SIZE", self.config.memory_size.to_string())
+            .envs(&self.config.environment)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+    }
+}
+
+/// Restarts the process of `worker` whenever it exits, and kills it when an invocation runs
+/// past its deadline.
+async fn supervise(worker: Arc<Worker>, process: Process, mut child: Child) {
+    loop {
+        let started_at = Instant::now();
+        let status = loop {
+            let deadline = worker.deadline();
+            tokio::select! {
+                status = child.wait() => break status,
+                _ = worker.started.notified() => {}
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    if worker.timed_out() {
+                        tracing::warn!(command = %process.config.command, "Invocation timed out, restarting the local runtime");
+                        worker.fail(|request_id| worker.timeout_error(request_id));
+                        let _ = child.start_kill();
+                    }
+                }
+            }
+        };
+
+        let reason = match &status {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        };
+        tracing::warn!(command = %process.config.command, %reason, "Local runtime exited");
+        worker.fail(|request_id| exit_error(request_id, &reason));
+
+        if started_at.elapsed() < RESTART_DELAY {
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+        child = loop {
+            match process.spawn() {
+                Ok(child) => break child,
+                Err(e) => {
+                    tracing::error!(command = %process.config.command, error = %e, "Failed to restart the local runtime");
+                    tokio::time::sleep(RESTART_DELAY).await;
+                }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("local_tests.rs");
+}
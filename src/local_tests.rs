@@ -0,0 +1,278 @@
+use super::*;
+use crate::config::PayloadFormat;
+use std::collections::HashMap;
+use axum::http::Request;
+use http_body::Frame;
+use http_body_util::StreamBody;
+use tower::ServiceExt;
+
+fn target() -> LambdaTarget {
+    LambdaTarget {
+        qualifier: Some("live".to_string()),
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..LambdaTarget::for_test("function")
+    }
+}
+
+/// An invoker and the Runtime API of a single process, without starting one.
+fn emulator(timeout: Duration) -> (Arc<LocalInvoker>, Router, Arc<Worker>) {
+    let (invocations, receiver) = mpsc::unbounded_channel();
+    let worker = Arc::new(Worker::new(Arc::new(tokio::sync::Mutex::new(receiver)), timeout));
+    (Arc::new(LocalInvoker { invocations }), runtime_api(worker.clone()), worker)
+}
+
+async fn next(runtime_api: &Router) -> (String, Response) {
+    let request = Request::get("/2018-06-01/runtime/invocation/next")
+        .body(Body::empty())
+        .unwrap();
+    let response = runtime_api.clone().oneshot(request).await.unwrap();
+    let request_id = response.headers()["lambda-runtime-aws-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    (request_id, response)
+}
+
+fn post(uri: String, body: Body) -> Request<Body> {
+    Request::post(uri).body(body).unwrap()
+}
+
+#[tokio::test]
+async fn test_buffered_invocation() {
+    let (invoker, runtime_api, _) = emulator(Duration::from_secs(30));
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke(&target(), b"{\"a\":1}".to_vec()).await }
+    });
+
+    let (request_id, response) = next(&runtime_api).await;
+    let function_arn = response.headers()["lambda-runtime-invoked-function-arn"].to_str().unwrap();
+    assert!(function_arn.ends_with(":000000000000:function:function:live"));
+    assert!(response.headers().contains_key("lambda-runtime-deadline-ms"));
+    let event = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(event, "{\"a\":1}");
+
+    let uri = format!("/2018-06-01/runtime/invocation/{}/response", request_id);
+    let response = runtime_api.clone().oneshot(post(uri, Body::from("ok"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    assert_eq!(invocation.await.unwrap().unwrap(), InvokeResponse::new("ok"));
+}
+
+#[tokio::test]
+async fn test_invocation_error() {
+    let (invoker, runtime_api, _) = emulator(Duration::from_secs(30));
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke(&target(), Vec::new()).await }
+    });
+
+    let (request_id, _) = next(&runtime_api).await;
+    let request = Request::post(format!("/2018-06-01/runtime/invocation/{}/error", request_id))
+        .header(ERROR_TYPE_HEADER, "Runtime.Panic")
+        .body(Body::from(r#"{"errorMessage": "boom"}"#))
+        .unwrap();
+    let response = runtime_api.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let response = invocation.await.unwrap().unwrap();
+    assert_eq!(response.function_error.as_deref(), Some("Unhandled"));
+    let error = FunctionError::parse("Unhandled", &response.payload);
+    assert_eq!(error.error_type, "Runtime.Panic");
+    assert_eq!(error.error_message, "boom");
+}
+
+#[tokio::test]
+async fn test_invalid_request_id() {
+    let (_, runtime_api, _) = emulator(Duration::from_secs(30));
+
+    let uri = "/2018-06-01/runtime/invocation/unknown/response".to_string();
+    let response = runtime_api.oneshot(post(uri, Body::from("ok"))).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_streamed_invocation() {
+    let (invoker, runtime_api, _) = emulator(Duration::from_secs(30));
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke_stream(&target(), Vec::new()).await }
+    });
+
+    let (request_id, _) = next(&runtime_api).await;
+    let chunks = stream::iter([Ok::<_, std::io::Error>("hel"), Ok("lo")]);
+    let request = Request::post(format!("/2018-06-01/runtime/invocation/{}/response", request_id))
+        .header(RESPONSE_MODE_HEADER, "streaming")
+        .header("content-type", "text/plain")
+        .body(Body::from_stream(chunks))
+        .unwrap();
+    let response = tokio::spawn(runtime_api.clone().oneshot(request));
+
+    let stream = invocation.await.unwrap().unwrap();
+    assert_eq!(stream.content_type.as_deref(), Some("text/plain"));
+    let events: Vec<_> = stream.events.collect().await;
+    assert_eq!(
+        events,
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(b"hel"))),
+            Ok(StreamEvent::Data(Bytes::from_static(b"lo"))),
+            Ok(StreamEvent::Complete(None)),
+        ]
+    );
+    assert_eq!(response.await.unwrap().unwrap().status(), StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn test_streamed_invocation_error_trailers() {
+    let (invoker, runtime_api, _) = emulator(Duration::from_secs(30));
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke_stream(&target(), Vec::new()).await }
+    });
+
+    let (request_id, _) = next(&runtime_api).await;
+    let mut trailers = HeaderMap::new();
+    trailers.insert(ERROR_TYPE_HEADER, "Runtime.Panic".parse().unwrap());
+    let error_body = base64::engine::general_purpose::STANDARD.encode(r#"{"errorMessage": "boom"}"#);
+    trailers.insert(ERROR_BODY_TRAILER, error_body.parse().unwrap());
+    let frames = stream::iter([
+        Ok::<_, std::io::Error>(Frame::data(Bytes::from_static(b"partial"))),
+        Ok(Frame::trailers(trailers)),
+    ]);
+    let request = Request::post(format!("/2018-06-01/runtime/invocation/{}/response", request_id))
+        .header(RESPONSE_MODE_HEADER, "streaming")
+        .body(Body::new(StreamBody::new(frames)))
+        .unwrap();
+    tokio::spawn(runtime_api.clone().oneshot(request));
+
+    let events: Vec<_> = invocation.await.unwrap().unwrap().events.collect().await;
+    let error = FunctionError {
+        error_type: "Runtime.Panic".to_string(),
+        error_message: "boom".to_string(),
+        stack_trace: Vec::new(),
+    };
+    assert_eq!(
+        events,
+        vec![
+            Ok(StreamEvent::Data(Bytes::from_static(b"partial"))),
+            Ok(StreamEvent::Complete(Some(error))),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_buffered_response_to_streamed_invocation() {
+    let (invoker, runtime_api, _) = emulator(Duration::from_secs(30));
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke_stream(&target(), Vec::new()).await }
+    });
+
+    let (request_id, _) = next(&runtime_api).await;
+    let uri = format!("/2018-06-01/runtime/invocation/{}/response", request_id);
+    runtime_api.clone().oneshot(post(uri, Body::from("ok"))).await.unwrap();
+
+    let events: Vec<_> = invocation.await.unwrap().unwrap().events.collect().await;
+    assert_eq!(
+        events,
+        vec![Ok(StreamEvent::Data(Bytes::from_static(b"ok"))), Ok(StreamEvent::Complete(None))]
+    );
+}
+
+#[tokio::test]
+async fn test_invocation_timeout() {
+    let (invoker, runtime_api, worker) = emulator(Duration::ZERO);
+    let invocation = tokio::spawn({
+        let invoker = invoker.clone();
+        async move { invoker.invoke(&target(), Vec::new()).await }
+    });
+
+    next(&runtime_api).await;
+    assert!(worker.timed_out());
+    worker.fail(|request_id| worker.timeout_error(request_id));
+
+    let response = invocation.await.unwrap().unwrap();
+    assert!(FunctionError::parse("Unhandled", &response.payload).is_timeout());
+    assert!(worker.deadline().is_none());
+}
+
+/// A runtime that answers each event with the event and its process id. The events `exit`, `sleep`
+/// and `slow` make it exit, hang past the timeout and take half a second.
+const RUNTIME_SCRIPT: &str = r#"
+api="http://$AWS_LAMBDA_RUNTIME_API/2018-06-01/runtime/invocation"
+headers=$(mktemp)
+while event=$(curl -sSf -D "$headers" "$api/next"); do
+    request_id=$(grep -i '^lambda-runtime-aws-request-id:' "$headers" | cut -d' ' -f2 | tr -d '\r')
+    case "$event" in
+        exit) exit 3 ;;
+        sleep) sleep 10 ;;
+        slow) sleep 0.5 ;;
+    esac
+    curl -sSf -X POST --data-binary "$event $$" "$api/$request_id/response" > /dev/null
+done
+"#;
+
+/// Starts `RUNTIME_SCRIPT` in `concurrency` processes. The script needs `curl`, so the tests fail without it.
+async fn start_script(concurrency: usize) -> (LocalInvoker, tempfile::NamedTempFile) {
+    let curl = std::process::Command::new("curl").arg("--version").output();
+    assert!(curl.is_ok(), "the local runtime tests need curl");
+    let script = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(script.path(), RUNTIME_SCRIPT).unwrap();
+    let config = LocalRuntimeConfig {
+        command: "sh".to_string(),
+        args: vec![script.path().to_str().unwrap().to_string()],
+        environment: HashMap::new(),
+        concurrency,
+        timeout: 1,
+        memory_size: 128,
+    };
+    let invoker = LocalInvoker::start(&config, "function").await.unwrap();
+    (invoker, script)
+}
+
+/// Invokes the script runtime and returns the event it echoed and the id of its process.
+async fn invoke_script(invoker: &LocalInvoker, event: &str) -> (String, String) {
+    let response = invoker.invoke(&target(), event.as_bytes().to_vec()).await.unwrap();
+    assert_eq!(response.function_error, None);
+    let payload = String::from_utf8(response.payload.to_vec()).unwrap();
+    let (event, pid) = payload.split_once(' ').unwrap();
+    (event.to_string(), pid.to_string())
+}
+
+#[tokio::test]
+async fn test_process_restarts_after_exit() {
+    let (invoker, _script) = start_script(1).await;
+    let (event, pid) = invoke_script(&invoker, "hello").await;
+    assert_eq!(event, "hello");
+
+    let response = invoker.invoke(&target(), b"exit".to_vec()).await.unwrap();
+    assert_eq!(response.function_error.as_deref(), Some("Unhandled"));
+    let error = FunctionError::parse("Unhandled", &response.payload);
+    assert_eq!(error.error_type, "Runtime.ExitError");
+
+    let (event, restarted) = invoke_script(&invoker, "hello").await;
+    assert_eq!(event, "hello");
+    assert_ne!(restarted, pid);
+}
+
+#[tokio::test]
+async fn test_process_killed_on_timeout() {
+    let (invoker, _script) = start_script(1).await;
+    let (_, pid) = invoke_script(&invoker, "hello").await;
+
+    let response = invoker.invoke(&target(), b"sleep".to_vec()).await.unwrap();
+    assert_eq!(response.function_error.as_deref(), Some("Unhandled"));
+    assert!(FunctionError::parse("Unhandled", &response.payload).is_timeout());
+
+    let (event, restarted) = invoke_script(&invoker, "hello").await;
+    assert_eq!(event, "hello");
+    assert_ne!(restarted, pid);
+}
+
+#[tokio::test]
+async fn test_processes_run_concurrently() {
+    let (invoker, _script) = start_script(2).await;
+    let ((_, first), (_, second)) = tokio::join!(invoke_script(&invoker, "slow"), invoke_script(&invoker, "slow"));
+    assert_ne!(first, second);
+}
@@ -0,0 +1,74 @@
+use crate::config::Config;
+use axum::http::HeaderMap;
+
+/// Decides whether a request body is sent to the function base64 encoded.
+///
+/// Compressed bodies are always binary. Otherwise the media type, without parameters such as
+/// `charset`, is checked against `binary_media_types` and then `text_media_types`. Text bodies
+/// that are not valid UTF-8 are still sent as binary so they reach the function unchanged.
+pub fn is_binary(headers: &HeaderMap, body: &[u8], config: &Config) -> bool {
+    let content_encoding = headers
+        .get("content-encoding")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .unwrap_or_default();
+    if !content_encoding.is_empty() && !content_encoding.eq_ignore_ascii_case("identity") {
+        return true;
+    }
+
+    let media_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(media_type)
+        .unwrap_or_default();
+
+    if config.binary_media_types.iter().any(|p| matches(p, &media_type)) {
+        return true;
+    }
+    if config.text_media_types.iter().any(|p| matches(p, &media_type)) {
+        return std::str::from_utf8(body).is_err();
+    }
+    true
+}
+
+/// The lowercase `type/subtype` of a `Content-Type` value, without parameters.
+pub fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Matches a media type against a pattern such as `image/*`, `*/*` or `application/*+json`.
+fn matches(pattern: &str, media_type: &str) -> bool {
+    wildcard_match(&pattern.trim().to_ascii_lowercase(), media_type)
+}
+
+/// Matches `text` against a case-sensitive pattern where `*` matches any run of characters.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard in the pattern.
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    include!("media_tests.rs");
+}
@@ -0,0 +1,77 @@
+use super::*;
+use axum::http::HeaderValue;
+
+fn headers(content_type: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-type", HeaderValue::from_str(content_type).unwrap());
+    headers
+}
+
+#[test]
+fn test_media_type() {
+    assert_eq!(media_type("Application/JSON; charset=UTF-8"), "application/json");
+    assert_eq!(media_type("text/plain"), "text/plain");
+    assert_eq!(media_type(""), "");
+}
+
+#[test]
+fn test_matches() {
+    assert!(matches("*/*", "image/png"));
+    assert!(matches("image/*", "image/png"));
+    assert!(!matches("image/*", "text/plain"));
+    assert!(matches("application/*+json", "application/vnd.api+json"));
+    assert!(!matches("application/*+json", "application/json"));
+    assert!(matches("application/json", "application/json"));
+    assert!(!matches("application/json", "application/jsonx"));
+    assert!(matches("a*b*c", "axxbyyc"));
+    assert!(!matches("a*b*c", "axxcyyb"));
+}
+
+#[test]
+fn test_is_binary_defaults() {
+    let config = Config::default();
+    assert!(!is_binary(&headers("application/json"), b"{}", &config));
+    assert!(!is_binary(&headers("text/html; charset=utf-8"), b"<p>", &config));
+    assert!(!is_binary(&headers("application/x-www-form-urlencoded"), b"a=1", &config));
+    assert!(!is_binary(&headers("application/graphql"), b"{ a }", &config));
+    assert!(!is_binary(&headers("application/vnd.api+json"), b"{}", &config));
+    assert!(is_binary(&headers("image/png"), b"\x89PNG", &config));
+    assert!(is_binary(&HeaderMap::new(), b"", &config));
+}
+
+#[test]
+fn test_is_binary_invalid_utf8_text() {
+    let config = Config::default();
+    assert!(is_binary(&headers("text/plain; charset=iso-8859-1"), b"caf\xe9", &config));
+}
+
+#[test]
+fn test_is_binary_content_encoding() {
+    let config = Config::default();
+    let mut headers = headers("application/json");
+    headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+    assert!(is_binary(&headers, b"{}", &config));
+    headers.insert("content-encoding", HeaderValue::from_static("identity"));
+    assert!(!is_binary(&headers, b"{}", &config));
+}
+
+#[test]
+fn test_is_binary_configured_types() {
+    let config = Config {
+        binary_media_types: vec!["application/json".to_string()],
+        text_media_types: vec!["application/octet-stream".to_string()],
+        ..Config::default()
+    };
+    assert!(is_binary(&headers("application/json"), b"{}", &config));
+    assert!(!is_binary(&headers("application/octet-stream"), b"text", &config));
+    assert!(is_binary(&headers("text/plain"), b"text", &config));
+}
+
+#[test]
+fn test_wildcard_match() {
+    assert!(wildcard_match("*", "Runtime.ExitError"));
+    assert!(wildcard_match("Runtime.*", "Runtime.ExitError"));
+    assert!(!wildcard_match("runtime.*", "Runtime.ExitError"));
+    assert!(wildcard_match("*Error", "TypeError"));
+    assert!(!wildcard_match("Error", "TypeError"));
+}
@@ -0,0 +1,112 @@
+use axum::http::StatusCode;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Gateway counters, exposed on `/metrics` in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    stream_client_disconnects: AtomicU64,
+    mirrors_dropped: AtomicU64,
+    /// Responses by function name, variant and status class.
+    variant_responses: Mutex<BTreeMap<(String, String, &'static str), u64>>,
+}
+
+impl Metrics {
+    /// Records a client that went away before a response stream finished.
+    pub fn record_stream_client_disconnect(&self) {
+        self.stream_client_disconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stream_client_disconnects(&self) -> u64 {
+        self.stream_client_disconnects.load(Ordering::Relaxed)
+    }
+
+    /// Records a sampled request that was not mirrored because the mirror was at `max_in_flight`.
+    pub fn record_mirror_dropped(&self) {
+        self.mirrors_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn mirrors_dropped(&self) -> u64 {
+        self.mirrors_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Records the response status of a request served by a canary route variant.
+    pub fn record_variant_response(&self, function_name: &str, variant: &str, status: StatusCode) {
+        let key = (function_name.to_string(), variant.to_string(), status_class(status));
+        *self.variant_responses.lock().unwrap().entry(key).or_default() += 1;
+    }
+
+    /// Responses of `variant` whose status is in `status_class`, e.g. `5xx`.
+    pub fn variant_responses(&self, function_name: &str, variant: &str, status_class: &str) -> u64 {
+        let variant_responses = self.variant_responses.lock().unwrap();
+        variant_responses
+            .iter()
+            .find(|((f, v, c), _)| f == function_name && v == variant && *c == status_class)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "gateway_stream_client_disconnects_total",
+            "Response streams cancelled because the client disconnected.",
+            self.stream_client_disconnects(),
+        );
+        write_counter(
+            &mut out,
+            "gateway_mirrors_dropped_total",
+            "Sampled requests not mirrored because the mirror had max_in_flight requests running.",
+            self.mirrors_dropped(),
+        );
+        let variant_responses = self.variant_responses.lock().unwrap();
+        if !variant_responses.is_empty() {
+            let name = "gateway_variant_responses_total";
+            let _ = writeln!(
+                out,
+                "# HELP {} Responses of canary route variants by status class.",
+                name
+            );
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for ((function_name, variant, status), count) in variant_responses.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}{{function=\"{}\",variant=\"{}\",status=\"{}\"}} {}",
+                    name,
+                    escape_label(function_name),
+                    escape_label(variant),
+                    status,
+                    count
+                );
+            }
+        }
+        out
+    }
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+#[cfg(test)]
+mod tests {
+    include!("metrics_tests.rs");
+}
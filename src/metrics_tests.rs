@@ -0,0 +1,43 @@
+use super::*;
+
+#[test]
+fn test_render() {
+    let metrics = Metrics::default();
+    metrics.record_stream_client_disconnect();
+    metrics.record_stream_client_disconnect();
+    metrics.record_mirror_dropped();
+
+    assert_eq!(metrics.stream_client_disconnects(), 2);
+    assert_eq!(metrics.mirrors_dropped(), 1);
+    assert_eq!(
+        metrics.render(),
+        "# HELP gateway_stream_client_disconnects_total Response streams cancelled because the client disconnected.\n\
+         # TYPE gateway_stream_client_disconnects_total counter\n\
+         gateway_stream_client_disconnects_total 2\n\
+         # HELP gateway_mirrors_dropped_total Sampled requests not mirrored because the mirror had max_in_flight requests running.\n\
+         # TYPE gateway_mirrors_dropped_total counter\n\
+         gateway_mirrors_dropped_total 1\n"
+    );
+}
+
+#[test]
+fn test_render_variant_responses() {
+    let metrics = Metrics::default();
+    metrics.record_variant_response("orders", "2", StatusCode::OK);
+    metrics.record_variant_response("orders", "2", StatusCode::BAD_GATEWAY);
+    metrics.record_variant_response("orders", "1", StatusCode::CREATED);
+    metrics.record_variant_response("orders", "1", StatusCode::OK);
+
+    assert_eq!(metrics.variant_responses("orders", "1", "2xx"), 2);
+    assert_eq!(metrics.variant_responses("orders", "2", "4xx"), 0);
+    let rendered = metrics.render();
+    let variants = rendered.split_once("gateway_mirrors_dropped_total 0\n").unwrap().1;
+    assert_eq!(
+        variants,
+        "# HELP gateway_variant_responses_total Responses of canary route variants by status class.\n\
+         # TYPE gateway_variant_responses_total counter\n\
+         gateway_variant_responses_total{function=\"orders\",variant=\"1\",status=\"2xx\"} 2\n\
+         gateway_variant_responses_total{function=\"orders\",variant=\"2\",status=\"2xx\"} 1\n\
+         gateway_variant_responses_total{function=\"orders\",variant=\"2\",status=\"5xx\"} 1\n"
+    );
+}
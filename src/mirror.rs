@@ -0,0 +1,124 @@
+use crate::config::{MirrorInvocationType, RouteConfig};
+use crate::invoker::Invoker;
+use crate::router::LambdaTarget;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
+
+/// A function sent copies of a route's request events. It gets the same event as the route's
+/// function; its results are logged and never affect the response.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    pub target: LambdaTarget,
+    pub invocation_type: MirrorInvocationType,
+    /// Share of requests mirrored, from 0 to 100.
+    pub percentage: u8,
+    /// Permits for `max_in_flight` mirrored requests.
+    in_flight: Arc<Semaphore>,
+}
+
+/// The status of an HTTP response returned by a mirrored function.
+#[derive(Deserialize)]
+struct MirroredResponse {
+    #[serde(rename = "statusCode")]
+    status_code: Option<u16>,
+}
+
+impl Mirror {
+    /// The mirror of `route`, invoked with the settings of its `target`. `None` when the route is
+    /// not mirrored.
+    pub(crate) fn from_config(route: &RouteConfig, target: &LambdaTarget) -> Result<Option<Self>, String> {
+        let Some(mirror) = &route.mirror else {
+            return Ok(None);
+        };
+        if mirror.lambda_function_name.is_empty() {
+            return Err(format!(
+                "No lambda_function_name provided for the mirror of route {}",
+                route.path
+            ));
+        }
+        if mirror.percentage > 100 {
+            return Err(format!("Mirror percentage above 100 for route {}", route.path));
+        }
+        if mirror.max_in_flight == 0 {
+            return Err(format!("Mirror max_in_flight of 0 for route {}", route.path));
+        }
+        Ok(Some(Mirror {
+            target: LambdaTarget {
+                function_name: mirror.lambda_function_name.clone(),
+                qualifier: mirror.qualifier.clone(),
+                ..target.clone()
+            },
+            invocation_type: mirror.invocation_type.clone(),
+            percentage: mirror.percentage,
+            in_flight: Arc::new(Semaphore::new(mirror.max_in_flight)),
+        }))
+    }
+
+    /// Whether to mirror the next request.
+    pub fn sample(&self) -> bool {
+        fastrand::u8(..100) < self.percentage
+    }
+
+    /// Invokes the mirror with a copy of `payload` in the background. `request_id` is the id of
+    /// the mirrored request, to match the logged outcome with the primary response.
+    ///
+    /// Returns `false`, without copying `payload`, when `max_in_flight` mirrored requests are
+    /// already running, so that a slow mirror cannot pile up tasks or slow down the primary.
+    pub fn send(self: &Arc<Self>, invoker: Arc<dyn Invoker>, request_id: &str, payload: &[u8]) -> bool {
+        let Ok(permit) = self.in_flight.clone().try_acquire_owned() else {
+            return false;
+        };
+        let mirror = self.clone();
+        let payload = payload.to_vec();
+        let span = tracing::info_span!("mirror", request_id, function_name = %self.target.function_name);
+        tokio::spawn(
+            async move {
+                mirror.invoke(invoker.as_ref(), payload).await;
+                drop(permit);
+            }
+            .instrument(span),
+        );
+        true
+    }
+
+    async fn invoke(&self, invoker: &dyn Invoker, payload: Vec<u8>) {
+        let started = Instant::now();
+        match self.invocation_type {
+            MirrorInvocationType::Event => {
+                let result = invoker.invoke_event(&self.target, payload).await;
+                let latency_ms = started.elapsed().as_millis() as u64;
+                match result {
+                    Ok(()) => tracing::info!(latency_ms, "Mirrored event accepted"),
+                    Err(e) => tracing::warn!(error = %e, latency_ms, "Mirrored event failed"),
+                }
+            }
+            MirrorInvocationType::RequestResponse => {
+                let result = invoker.invoke(&self.target, payload).await;
+                let latency_ms = started.elapsed().as_millis() as u64;
+                match result {
+                    Ok(response) => {
+                        let status = serde_json::from_slice::<MirroredResponse>(&response.payload)
+                            .ok()
+                            .and_then(|response| response.status_code);
+                        tracing::info!(
+                            status,
+                            lambda_status = response.status_code,
+                            function_error = response.function_error.as_deref(),
+                            latency_ms,
+                            "Mirrored request completed"
+                        );
+                    }
+                    Err(e) => tracing::warn!(error = %e, latency_ms, "Mirrored request failed"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("mirror_tests.rs");
+}
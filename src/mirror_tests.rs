@@ -0,0 +1,121 @@
+use super::*;
+use crate::config::{LambdaInvokeMode, MirrorConfig, PayloadFormat};
+use crate::error::GatewayError;
+use crate::invoker::{InvokeResponse, MockInvoker};
+
+fn target() -> LambdaTarget {
+    LambdaTarget {
+        qualifier: Some("live".to_string()),
+        invoke_mode: LambdaInvokeMode::ResponseStream,
+        payload_format: PayloadFormat::ApiGatewayV2,
+        ..LambdaTarget::for_test("orders")
+    }
+}
+
+fn route(mirror: Option<MirrorConfig>) -> RouteConfig {
+    let mut route: RouteConfig = serde_yaml::from_str("{path: /orders, lambda_function_name: orders}").unwrap();
+    route.mirror = mirror;
+    route
+}
+
+fn mirror(invocation_type: MirrorInvocationType, percentage: u8) -> Mirror {
+    let config = MirrorConfig {
+        lambda_function_name: "orders-v2".to_string(),
+        qualifier: None,
+        invocation_type,
+        percentage,
+        max_in_flight: 1,
+    };
+    Mirror::from_config(&route(Some(config)), &target()).unwrap().unwrap()
+}
+
+#[test]
+fn test_from_config() {
+    assert!(Mirror::from_config(&route(None), &target()).unwrap().is_none());
+
+    let mirror = mirror(MirrorInvocationType::Event, 10);
+    assert_eq!(mirror.target.function_name, "orders-v2");
+    assert_eq!(mirror.target.qualifier, None);
+    assert_eq!(mirror.target.payload_format, PayloadFormat::ApiGatewayV2);
+
+    let mut config: MirrorConfig = serde_yaml::from_str("{lambda_function_name: orders-v2}").unwrap();
+    assert_eq!(config.invocation_type, MirrorInvocationType::Event);
+    assert_eq!(config.percentage, 100);
+    assert_eq!(config.max_in_flight, 64);
+    config.max_in_flight = 0;
+    assert_eq!(
+        Mirror::from_config(&route(Some(config.clone())), &target()).unwrap_err(),
+        "Mirror max_in_flight of 0 for route /orders"
+    );
+    config.max_in_flight = 64;
+    config.percentage = 101;
+    assert_eq!(
+        Mirror::from_config(&route(Some(config.clone())), &target()).unwrap_err(),
+        "Mirror percentage above 100 for route /orders"
+    );
+    config.percentage = 100;
+    config.lambda_function_name = String::new();
+    assert_eq!(
+        Mirror::from_config(&route(Some(config)), &target()).unwrap_err(),
+        "No lambda_function_name provided for the mirror of route /orders"
+    );
+}
+
+#[test]
+fn test_sample() {
+    let never = mirror(MirrorInvocationType::Event, 0);
+    let always = mirror(MirrorInvocationType::Event, 100);
+    for _ in 0..100 {
+        assert!(!never.sample());
+        assert!(always.sample());
+    }
+}
+
+#[tokio::test]
+async fn test_invoke() {
+    let invoker = MockInvoker::new().with_response("orders-v2", InvokeResponse::new(r#"{"statusCode": 201}"#));
+
+    mirror(MirrorInvocationType::Event, 100)
+        .invoke(&invoker, b"event".to_vec())
+        .await;
+    mirror(MirrorInvocationType::RequestResponse, 100)
+        .invoke(&invoker, b"event".to_vec())
+        .await;
+
+    let invocations = invoker.invocations();
+    assert_eq!(invocations.len(), 2);
+    assert!(invocations
+        .iter()
+        .all(|i| i.function_name == "orders-v2" && i.payload == b"event"));
+}
+
+#[tokio::test]
+async fn test_invoke_error_is_logged() {
+    let invoker = MockInvoker::new().with_error("orders-v2", GatewayError::Throttled("slow down".to_string()));
+
+    mirror(MirrorInvocationType::RequestResponse, 100)
+        .invoke(&invoker, Vec::new())
+        .await;
+
+    assert_eq!(invoker.invocations().len(), 1);
+}
+
+#[tokio::test]
+async fn test_send_max_in_flight() {
+    let invoker = Arc::new(MockInvoker::new());
+    let mirror = Arc::new(mirror(MirrorInvocationType::Event, 100));
+
+    let permit = mirror.in_flight.clone().try_acquire_owned().unwrap();
+    assert!(!mirror.send(invoker.clone(), "1", b"event"));
+    drop(permit);
+    assert!(mirror.send(invoker.clone(), "2", b"event"));
+
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while mirror.in_flight.available_permits() == 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(invoker.invocations().len(), 1);
+}
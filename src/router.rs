@@ -0,0 +1,563 @@
+use crate::canary::Canary;
+use crate::config::{
+    AuthMode, Config, LambdaClientConfig, LambdaInvokeMode, PayloadFormat, PreludeDetection, RouteConfig,
+};
+use crate::fallback::Fallback;
+use crate::mirror::Mirror;
+use crate::template::Templates;
+use axum::http::Method;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    include!("router_tests.rs");
+}
+
+/// The Lambda function a request is dispatched to.
+#[derive(Clone, Debug)]
+pub struct LambdaTarget {
+    pub function_name: String,
+    pub qualifier: Option<String>,
+    pub invoke_mode: LambdaInvokeMode,
+    pub payload_format: PayloadFormat,
+    pub multi_value_headers: bool,
+    pub raw_content_type: String,
+    pub prelude_detection: PreludeDetection,
+    pub stream_idle_timeout: Option<Duration>,
+    pub stream_max_duration: Option<Duration>,
+    pub sse: bool,
+    /// `None` when keepalives are disabled.
+    pub sse_keepalive_interval: Option<Duration>,
+    pub lambda_client: LambdaClientConfig,
+    /// Regions tried in order, overriding the client's region. Empty to use the client's region.
+    pub regions: Vec<String>,
+    pub templates: Option<Arc<Templates>>,
+}
+
+impl LambdaTarget {
+    /// The target built from the top-level configuration.
+    fn from_config(config: &Config) -> Result<Self, String> {
+        Ok(LambdaTarget {
+            function_name: config.lambda_function_name.clone(),
+            qualifier: None,
+            invoke_mode: config.lambda_invoke_mode.clone(),
+            payload_format: config.payload_format.clone(),
+            multi_value_headers: config.multi_value_headers,
+            raw_content_type: config.raw_content_type.clone(),
+            prelude_detection: config.prelude_detection.clone(),
+            stream_idle_timeout: config.stream_idle_timeout.map(Duration::from_secs),
+            stream_max_duration: config.stream_max_duration.map(Duration::from_secs),
+            sse: config.sse,
+            sse_keepalive_interval: keepalive_interval(config.sse_keepalive_interval),
+            lambda_client: config.lambda_client.clone(),
+            regions: config.regions.clone(),
+            templates: resolve_templates(
+                config.request_template.as_ref(),
+                config.response_template.as_ref(),
+                None,
+            )?,
+        })
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if self.sse && self.invoke_mode != LambdaInvokeMode::ResponseStream {
+            return Err(format!("sse requires the ResponseStream invoke mode for {}", name));
+        }
+        let mut regions = HashSet::new();
+        if let Some(region) = self.regions.iter().find(|r| !regions.insert(*r)) {
+            return Err(format!("Region {} listed more than once for {}", region, name));
+        }
+        if self.payload_format != PayloadFormat::Template {
+            return Ok(());
+        }
+        match &self.templates {
+            Some(templates) if templates.request_source().is_some() => {}
+            _ => return Err(format!("No request_template provided for {}", name)),
+        }
+        if self.invoke_mode == LambdaInvokeMode::ResponseStream
+            && self.templates.as_ref().is_some_and(|t| t.has_response())
+        {
+            return Err(format!(
+                "response_template is not supported with ResponseStream for {}",
+                name
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl LambdaTarget {
+    /// A target invoking `function_name` with the default settings.
+    pub(crate) fn for_test(function_name: &str) -> Self {
+        LambdaTarget {
+            function_name: function_name.to_string(),
+            ..LambdaTarget::from_config(&Config::default()).unwrap()
+        }
+    }
+}
+
+fn keepalive_interval(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+/// Compiles the templates of a route or virtual host. When neither template is set the
+/// inherited templates are kept; otherwise the one that is not set is inherited.
+fn resolve_templates(
+    request: Option<&String>,
+    response: Option<&String>,
+    defaults: Option<&Arc<Templates>>,
+) -> Result<Option<Arc<Templates>>, String> {
+    if request.is_none() && response.is_none() {
+        return Ok(defaults.cloned());
+    }
+    let request = request
+        .map(String::as_str)
+        .or_else(|| defaults.and_then(|t| t.request_source()));
+    let response = response
+        .map(String::as_str)
+        .or_else(|| defaults.and_then(|t| t.response_source()));
+    Ok(Some(Arc::new(Templates::new(request, response)?)))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Greedy(String),
+}
+
+impl Segment {
+    /// Rank used to order routes: literal segments win over `{param}`, which wins over `{proxy+}`.
+    fn rank(&self) -> u8 {
+        match self {
+            Segment::Literal(_) => 3,
+            Segment::Param(_) => 2,
+            Segment::Greedy(_) => 1,
+        }
+    }
+}
+
+/// A route path such as `/users/{id}/orders` or `/static/{proxy+}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathPattern {
+    raw: String,
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// Matches `path` against the pattern, returning the captured path parameters.
+    pub fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut params = HashMap::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.get(i)?.to_string());
+                }
+                Segment::Greedy(name) => {
+                    if i >= parts.len() {
+                        return None;
+                    }
+                    params.insert(name.clone(), parts[i..].join("/"));
+                    return Some(params);
+                }
+            }
+        }
+
+        (parts.len() == self.segments.len()).then_some(params)
+    }
+
+    fn precedence(&self) -> Vec<u8> {
+        self.segments.iter().map(Segment::rank).collect()
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('/') {
+            return Err(format!("Route path must start with '/': {}", s));
+        }
+
+        let parts: Vec<&str> = s.split('/').filter(|p| !p.is_empty()).collect();
+        let mut segments = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let segment = match part.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                Some(name) if name.ends_with('+') => {
+                    if i != parts.len() - 1 {
+                        return Err(format!("Greedy segment must be the last one in route path: {}", s));
+                    }
+                    Segment::Greedy(name.trim_end_matches('+').to_string())
+                }
+                Some(name) if !name.is_empty() => Segment::Param(name.to_string()),
+                Some(_) => return Err(format!("Empty path parameter in route path: {}", s)),
+                None => Segment::Literal(part.to_string()),
+            };
+            segments.push(segment);
+        }
+
+        Ok(PathPattern {
+            raw: s.to_string(),
+            segments,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub pattern: PathPattern,
+    /// Allowed methods. An empty list accepts any method.
+    pub methods: Vec<Method>,
+    pub target: LambdaTarget,
+    /// Qualifiers sharing the route's traffic, used instead of `target`.
+    pub canary: Option<Canary>,
+    pub mirror: Option<Arc<Mirror>>,
+    pub fallback: Option<Fallback>,
+}
+
+impl Route {
+    /// Builds a route; settings the route leaves unset are taken from `defaults`.
+    fn from_config(route: &RouteConfig, defaults: &LambdaTarget) -> Result<Self, String> {
+        let pattern = route.path.parse()?;
+        let mut methods = Vec::new();
+        for method in &route.methods {
+            let method = method.to_uppercase();
+            if method == "ANY" {
+                methods.clear();
+                break;
+            }
+            methods.push(
+                Method::from_str(&method).map_err(|_| format!("Invalid method in route {}: {}", route.path, method))?,
+            );
+        }
+        if route.lambda_function_name.is_empty() {
+            return Err(format!("No lambda_function_name provided for route {}", route.path));
+        }
+
+        let target = LambdaTarget {
+            function_name: route.lambda_function_name.clone(),
+            qualifier: route.qualifier.clone(),
+            invoke_mode: route
+                .lambda_invoke_mode
+                .clone()
+                .unwrap_or_else(|| defaults.invoke_mode.clone()),
+            payload_format: route
+                .payload_format
+                .clone()
+                .unwrap_or_else(|| defaults.payload_format.clone()),
+            multi_value_headers: route.multi_value_headers.unwrap_or(defaults.multi_value_headers),
+            raw_content_type: route
+                .raw_content_type
+                .clone()
+                .unwrap_or_else(|| defaults.raw_content_type.clone()),
+            prelude_detection: route
+                .prelude_detection
+                .clone()
+                .unwrap_or_else(|| defaults.prelude_detection.clone()),
+            stream_idle_timeout: route
+                .stream_idle_timeout
+                .map(Duration::from_secs)
+                .or(defaults.stream_idle_timeout),
+            stream_max_duration: route
+                .stream_max_duration
+                .map(Duration::from_secs)
+                .or(defaults.stream_max_duration),
+            sse: route.sse.unwrap_or(defaults.sse),
+            sse_keepalive_interval: route
+                .sse_keepalive_interval
+                .map_or(defaults.sse_keepalive_interval, keepalive_interval),
+            lambda_client: route.lambda_client.or(&defaults.lambda_client),
+            regions: route.regions.clone().unwrap_or_else(|| defaults.regions.clone()),
+            templates: resolve_templates(
+                route.request_template.as_ref(),
+                route.response_template.as_ref(),
+                defaults.templates.as_ref(),
+            )?,
+        };
+        Ok(Route {
+            pattern,
+            methods,
+            canary: Canary::from_config(route, &target)?,
+            mirror: Mirror::from_config(route, &target)?.map(Arc::new),
+            fallback: Fallback::from_config(route, &target)?,
+            target,
+        })
+    }
+
+    fn allows(&self, method: &Method) -> bool {
+        self.methods.is_empty() || self.methods.contains(method)
+    }
+}
+
+/// A successful route lookup.
+#[derive(Debug)]
+pub struct RouteMatch<'a> {
+    /// The matched route pattern, or `None` when the request fell through to the default function.
+    pub resource: Option<&'a str>,
+    pub path_parameters: HashMap<String, String>,
+    pub target: &'a LambdaTarget,
+    pub canary: Option<&'a Canary>,
+    pub mirror: Option<&'a Arc<Mirror>>,
+    pub fallback: Option<&'a Fallback>,
+}
+
+#[derive(Debug)]
+pub enum RouteLookup<'a> {
+    Matched(RouteMatch<'a>),
+    MethodNotAllowed,
+    NotFound,
+}
+
+/// Routes ordered by precedence, plus the optional catch-all function from `lambda_function_name`.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTable {
+    routes: Vec<Route>,
+    default: Option<LambdaTarget>,
+}
+
+impl RouteTable {
+    pub fn new(config: &Config) -> Result<Self, String> {
+        Self::from_routes(&config.routes, LambdaTarget::from_config(config)?)
+    }
+
+    /// `defaults` supplies settings routes leave unset, and is also the catch-all target when
+    /// its function name is not empty.
+    fn from_routes(routes: &[RouteConfig], defaults: LambdaTarget) -> Result<Self, String> {
+        let mut routes = routes
+            .iter()
+            .map(|route| Route::from_config(route, &defaults))
+            .collect::<Result<Vec<_>, _>>()?;
+        for route in &routes {
+            route.target.validate(&format!("route {}", route.pattern.as_str()))?;
+        }
+        if !defaults.function_name.is_empty() {
+            defaults.validate(&defaults.function_name)?;
+        }
+        // Most specific pattern first; on a tie, routes restricted to specific methods come first.
+        routes.sort_by(|a, b| {
+            b.pattern.precedence().cmp(&a.pattern.precedence()).then_with(|| {
+                match (a.methods.is_empty(), b.methods.is_empty()) {
+                    (false, true) => Ordering::Less,
+                    (true, false) => Ordering::Greater,
+                    _ => Ordering::Equal,
+                }
+            })
+        });
+
+        let default = (!defaults.function_name.is_empty()).then_some(defaults);
+
+        Ok(RouteTable { routes, default })
+    }
+
+    /// Every target of the table, including the catch-all one.
+    pub fn targets(&self) -> impl Iterator<Item = &LambdaTarget> {
+        self.routes
+            .iter()
+            .flat_map(|route| {
+                let variants = route.canary.iter().flat_map(|canary| canary.variants());
+                let mirror = route.mirror.iter().map(|mirror| &mirror.target);
+                let fallback = route.fallback.iter().filter_map(|fallback| fallback.target());
+                std::iter::once(&route.target)
+                    .chain(variants.map(|variant| &variant.target))
+                    .chain(mirror)
+                    .chain(fallback)
+            })
+            .chain(&self.default)
+    }
+
+    pub fn find(&self, method: &Method, path: &str) -> RouteLookup<'_> {
+        let mut path_matched = false;
+        for route in &self.routes {
+            if let Some(path_parameters) = route.pattern.matches(path) {
+                if route.allows(method) {
+                    return RouteLookup::Matched(RouteMatch {
+                        resource: Some(route.pattern.as_str()),
+                        path_parameters,
+                        target: &route.target,
+                        canary: route.canary.as_ref(),
+                        mirror: route.mirror.as_ref(),
+                        fallback: route.fallback.as_ref(),
+                    });
+                }
+                path_matched = true;
+            }
+        }
+
+        match &self.default {
+            Some(target) => RouteLookup::Matched(RouteMatch {
+                resource: None,
+                path_parameters: HashMap::new(),
+                target,
+                canary: None,
+                mirror: None,
+                fallback: None,
+            }),
+            None if path_matched => RouteLookup::MethodNotAllowed,
+            None => RouteLookup::NotFound,
+        }
+    }
+}
+
+/// Route table and authentication settings for one or more host names.
+#[derive(Clone, Debug)]
+pub struct VirtualHost {
+    pub routes: RouteTable,
+    pub auth_mode: AuthMode,
+    pub api_keys: HashSet<String>,
+}
+
+/// Selects the [`VirtualHost`] for a request by its `Host` header.
+///
+/// Exact host names are tried first, then `*.` wildcards (longest suffix wins), then the
+/// default host built from the top-level configuration.
+#[derive(Clone, Debug)]
+pub struct HostRouter {
+    hosts: Vec<VirtualHost>,
+    exact: HashMap<String, usize>,
+    wildcards: Vec<(String, usize)>,
+    default: VirtualHost,
+}
+
+impl HostRouter {
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let global = LambdaTarget::from_config(config)?;
+        let default = VirtualHost {
+            routes: RouteTable::from_routes(&config.routes, global.clone())?,
+            auth_mode: config.auth_mode.clone(),
+            api_keys: config.api_keys.clone(),
+        };
+
+        let mut hosts = Vec::with_capacity(config.virtual_hosts.len());
+        let mut exact = HashMap::new();
+        let mut wildcards = Vec::new();
+        for (index, vhost) in config.virtual_hosts.iter().enumerate() {
+            if vhost.hosts.is_empty() {
+                return Err("Virtual host without any hosts".to_string());
+            }
+            if vhost.lambda_function_name.is_empty() && vhost.routes.is_empty() {
+                return Err(format!(
+                    "No lambda_function_name or routes provided for virtual host {}",
+                    vhost.hosts.join(", ")
+                ));
+            }
+
+            let defaults = LambdaTarget {
+                function_name: vhost.lambda_function_name.clone(),
+                qualifier: None,
+                invoke_mode: vhost
+                    .lambda_invoke_mode
+                    .clone()
+                    .unwrap_or_else(|| global.invoke_mode.clone()),
+                payload_format: vhost
+                    .payload_format
+                    .clone()
+                    .unwrap_or_else(|| global.payload_format.clone()),
+                multi_value_headers: vhost.multi_value_headers.unwrap_or(global.multi_value_headers),
+                raw_content_type: vhost
+                    .raw_content_type
+                    .clone()
+                    .unwrap_or_else(|| global.raw_content_type.clone()),
+                prelude_detection: vhost
+                    .prelude_detection
+                    .clone()
+                    .unwrap_or_else(|| global.prelude_detection.clone()),
+                stream_idle_timeout: vhost
+                    .stream_idle_timeout
+                    .map(Duration::from_secs)
+                    .or(global.stream_idle_timeout),
+                stream_max_duration: vhost
+                    .stream_max_duration
+                    .map(Duration::from_secs)
+                    .or(global.stream_max_duration),
+                sse: vhost.sse.unwrap_or(global.sse),
+                sse_keepalive_interval: vhost
+                    .sse_keepalive_interval
+                    .map_or(global.sse_keepalive_interval, keepalive_interval),
+                lambda_client: vhost.lambda_client.or(&global.lambda_client),
+                regions: vhost.regions.clone().unwrap_or_else(|| global.regions.clone()),
+                templates: resolve_templates(
+                    vhost.request_template.as_ref(),
+                    vhost.response_template.as_ref(),
+                    global.templates.as_ref(),
+                )?,
+            };
+            hosts.push(VirtualHost {
+                routes: RouteTable::from_routes(&vhost.routes, defaults)?,
+                auth_mode: vhost.auth_mode.clone().unwrap_or_else(|| config.auth_mode.clone()),
+                api_keys: vhost.api_keys.clone().unwrap_or_else(|| config.api_keys.clone()),
+            });
+
+            for host in &vhost.hosts {
+                let host = host.to_lowercase();
+                let duplicate = match host.strip_prefix('*') {
+                    Some(suffix) if suffix.starts_with('.') => {
+                        let duplicate = wildcards.iter().any(|(s, _)| s == suffix);
+                        wildcards.push((suffix.to_string(), index));
+                        duplicate
+                    }
+                    Some(_) => return Err(format!("Invalid wildcard host: {}", host)),
+                    None => exact.insert(host.clone(), index).is_some(),
+                };
+                if duplicate {
+                    return Err(format!("Host configured more than once: {}", host));
+                }
+            }
+        }
+        wildcards.sort_by_key(|(suffix, _)| std::cmp::Reverse(suffix.len()));
+
+        Ok(HostRouter {
+            hosts,
+            exact,
+            wildcards,
+            default,
+        })
+    }
+
+    /// Every target of every virtual host.
+    pub fn targets(&self) -> impl Iterator<Item = &LambdaTarget> {
+        self.hosts
+            .iter()
+            .chain([&self.default])
+            .flat_map(|host| host.routes.targets())
+    }
+
+    /// Resolves a `Host` header value, which may carry a port, to its virtual host.
+    pub fn resolve(&self, host: Option<&str>) -> &VirtualHost {
+        let Some(host) = host.map(strip_port).map(str::to_lowercase) else {
+            return &self.default;
+        };
+
+        if let Some(&index) = self.exact.get(&host) {
+            return &self.hosts[index];
+        }
+        self.wildcards
+            .iter()
+            .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+            .map(|&(_, index)| &self.hosts[index])
+            .unwrap_or(&self.default)
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    // IPv6 literals keep their brackets, e.g. `[::1]:8000`.
+    if let Some(end) = host.find(']') {
+        return &host[..=end];
+    }
+    host.split(':').next().unwrap_or(host)
+}
@@ -0,0 +1,453 @@
+use super::*;
+use crate::config::{
+    AuthMode, Config, LambdaClientConfig, LambdaInvokeMode, PayloadFormat, PreludeDetection, RouteConfig,
+    VariantConfig, VirtualHostConfig,
+};
+use std::collections::HashSet;
+
+fn route(path: &str, methods: &[&str], function: &str) -> RouteConfig {
+    RouteConfig {
+        path: path.to_string(),
+        methods: methods.iter().map(|m| m.to_string()).collect(),
+        lambda_function_name: function.to_string(),
+        qualifier: None,
+        variants: Vec::new(),
+        sticky: None,
+        mirror: None,
+        fallback: None,
+        lambda_invoke_mode: None,
+        payload_format: None,
+        multi_value_headers: None,
+        raw_content_type: None,
+        prelude_detection: None,
+        stream_idle_timeout: None,
+        stream_max_duration: None,
+        sse: None,
+        sse_keepalive_interval: None,
+        lambda_client: Default::default(),
+        regions: None,
+        request_template: None,
+        response_template: None,
+    }
+}
+
+#[test]
+fn test_path_pattern_parse() {
+    assert!("/users/{id}".parse::<PathPattern>().is_ok());
+    assert!("/static/{proxy+}".parse::<PathPattern>().is_ok());
+    assert!("users".parse::<PathPattern>().is_err());
+    assert!("/{proxy+}/users".parse::<PathPattern>().is_err());
+    assert!("/users/{}".parse::<PathPattern>().is_err());
+}
+
+#[test]
+fn test_path_pattern_matches() {
+    let pattern: PathPattern = "/users/{id}/orders".parse().unwrap();
+    let params = pattern.matches("/users/42/orders").unwrap();
+    assert_eq!(params.get("id"), Some(&"42".to_string()));
+    assert!(pattern.matches("/users/42").is_none());
+    assert!(pattern.matches("/users/42/orders/1").is_none());
+
+    let pattern: PathPattern = "/static/{proxy+}".parse().unwrap();
+    let params = pattern.matches("/static/css/site.css").unwrap();
+    assert_eq!(params.get("proxy"), Some(&"css/site.css".to_string()));
+    assert!(pattern.matches("/static").is_none());
+
+    let pattern: PathPattern = "/".parse().unwrap();
+    assert!(pattern.matches("/").is_some());
+    assert!(pattern.matches("/users").is_none());
+}
+
+#[test]
+fn test_route_table_longest_match() {
+    let config = Config {
+        routes: vec![
+            route("/{proxy+}", &[], "catch-all"),
+            route("/users/{id}", &[], "user"),
+            route("/users/me", &[], "me"),
+            route("/users/{proxy+}", &[], "users-greedy"),
+        ],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    let lookup = |path: &str| match table.find(&Method::GET, path) {
+        RouteLookup::Matched(m) => m.target.function_name.clone(),
+        other => panic!("unexpected lookup result {:?}", other),
+    };
+
+    assert_eq!(lookup("/users/me"), "me");
+    assert_eq!(lookup("/users/42"), "user");
+    assert_eq!(lookup("/users/42/orders"), "users-greedy");
+    assert_eq!(lookup("/orders"), "catch-all");
+}
+
+#[test]
+fn test_route_table_methods() {
+    let config = Config {
+        routes: vec![
+            route("/items", &["GET"], "list-items"),
+            route("/items", &["post", "PUT"], "write-items"),
+        ],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    match table.find(&Method::PUT, "/items") {
+        RouteLookup::Matched(m) => assert_eq!(m.target.function_name, "write-items"),
+        other => panic!("unexpected lookup result {:?}", other),
+    }
+    assert!(matches!(table.find(&Method::DELETE, "/items"), RouteLookup::MethodNotAllowed));
+    assert!(matches!(table.find(&Method::GET, "/other"), RouteLookup::NotFound));
+}
+
+#[test]
+fn test_route_table_default_function() {
+    let mut routed = route("/orders/{id}", &[], "orders");
+    routed.qualifier = Some("live".to_string());
+    routed.lambda_invoke_mode = Some(LambdaInvokeMode::ResponseStream);
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        routes: vec![routed],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    match table.find(&Method::GET, "/orders/7") {
+        RouteLookup::Matched(m) => {
+            assert_eq!(m.resource, Some("/orders/{id}"));
+            assert_eq!(m.target.qualifier.as_deref(), Some("live"));
+            assert_eq!(m.target.invoke_mode, LambdaInvokeMode::ResponseStream);
+        }
+        other => panic!("unexpected lookup result {:?}", other),
+    }
+    match table.find(&Method::GET, "/") {
+        RouteLookup::Matched(m) => {
+            assert_eq!(m.resource, None);
+            assert_eq!(m.target.function_name, "default-function");
+            assert_eq!(m.target.invoke_mode, LambdaInvokeMode::Buffered);
+        }
+        other => panic!("unexpected lookup result {:?}", other),
+    }
+}
+
+#[test]
+fn test_route_table_invalid_route() {
+    let config = Config {
+        routes: vec![route("/items", &["FETCH ME"], "items")],
+        ..Config::default()
+    };
+    assert!(RouteTable::new(&config).is_err());
+
+    let config = Config {
+        routes: vec![route("/items", &[], "")],
+        ..Config::default()
+    };
+    assert!(RouteTable::new(&config).is_err());
+}
+
+fn vhost(hosts: &[&str], function: &str) -> VirtualHostConfig {
+    VirtualHostConfig {
+        hosts: hosts.iter().map(|h| h.to_string()).collect(),
+        lambda_function_name: function.to_string(),
+        lambda_invoke_mode: None,
+        payload_format: None,
+        multi_value_headers: None,
+        raw_content_type: None,
+        prelude_detection: None,
+        stream_idle_timeout: None,
+        stream_max_duration: None,
+        sse: None,
+        sse_keepalive_interval: None,
+        lambda_client: Default::default(),
+        regions: None,
+        request_template: None,
+        response_template: None,
+        routes: Vec::new(),
+        auth_mode: None,
+        api_keys: None,
+    }
+}
+
+fn default_function(vhost: &VirtualHost) -> String {
+    match vhost.routes.find(&Method::GET, "/") {
+        RouteLookup::Matched(m) => m.target.function_name.clone(),
+        other => panic!("unexpected lookup result {:?}", other),
+    }
+}
+
+#[test]
+fn test_host_router_resolve() {
+    let mut b = vhost(&["api.b.com"], "b-function");
+    b.auth_mode = Some(AuthMode::ApiKey);
+    b.api_keys = Some(HashSet::from(["b-key".to_string()]));
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        virtual_hosts: vec![
+            vhost(&["api.a.com"], "a-function"),
+            b,
+            vhost(&["*.example.com"], "example-function"),
+            vhost(&["*.eu.example.com"], "eu-function"),
+        ],
+        ..Config::default()
+    };
+    let router = HostRouter::new(&config).unwrap();
+
+    assert_eq!(default_function(router.resolve(Some("api.a.com"))), "a-function");
+    assert_eq!(default_function(router.resolve(Some("API.A.COM:8443"))), "a-function");
+    assert_eq!(default_function(router.resolve(Some("shop.example.com"))), "example-function");
+    assert_eq!(default_function(router.resolve(Some("shop.eu.example.com"))), "eu-function");
+    assert_eq!(default_function(router.resolve(Some("example.com"))), "default-function");
+    assert_eq!(default_function(router.resolve(Some("[::1]:8000"))), "default-function");
+    assert_eq!(default_function(router.resolve(None)), "default-function");
+
+    let b = router.resolve(Some("api.b.com"));
+    assert_eq!(b.auth_mode, AuthMode::ApiKey);
+    assert!(b.api_keys.contains("b-key"));
+    assert_eq!(router.resolve(Some("api.a.com")).auth_mode, AuthMode::Open);
+}
+
+#[test]
+fn test_host_router_invalid_hosts() {
+    let config = Config {
+        virtual_hosts: vec![vhost(&["api.a.com"], "a"), vhost(&["API.a.com"], "b")],
+        ..Config::default()
+    };
+    assert!(HostRouter::new(&config).is_err());
+
+    let config = Config {
+        virtual_hosts: vec![vhost(&["*example.com"], "a")],
+        ..Config::default()
+    };
+    assert!(HostRouter::new(&config).is_err());
+
+    let config = Config {
+        virtual_hosts: vec![vhost(&["api.a.com"], "")],
+        ..Config::default()
+    };
+    assert!(HostRouter::new(&config).is_err());
+}
+
+#[test]
+fn test_route_table_templates() {
+    let mut with_response = route("/orders", &[], "orders");
+    with_response.response_template = Some(r#"{"statusCode": 200, "body": {{ body|tojson }}}"#.to_string());
+    let config = Config {
+        payload_format: PayloadFormat::Template,
+        request_template: Some("{{ body }}".to_string()),
+        routes: vec![route("/items", &[], "items"), with_response],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    let templates = |path: &str| match table.find(&Method::GET, path) {
+        RouteLookup::Matched(m) => m.target.templates.clone().unwrap(),
+        other => panic!("unexpected lookup result {:?}", other),
+    };
+    assert!(!templates("/items").has_response());
+    assert!(templates("/orders").has_response());
+    assert_eq!(templates("/orders").request_source(), Some("{{ body }}"));
+}
+
+#[test]
+fn test_route_table_invalid_templates() {
+    let mut templated = route("/items", &[], "items");
+    templated.payload_format = Some(PayloadFormat::Template);
+    let config = Config {
+        routes: vec![templated.clone()],
+        ..Config::default()
+    };
+    assert!(RouteTable::new(&config).is_err());
+
+    templated.request_template = Some("{{ body }}".to_string());
+    templated.response_template = Some("{{ body }}".to_string());
+    templated.lambda_invoke_mode = Some(LambdaInvokeMode::ResponseStream);
+    let config = Config {
+        routes: vec![templated],
+        ..Config::default()
+    };
+    assert!(RouteTable::new(&config).is_err());
+}
+
+#[test]
+fn test_host_router_prelude_detection() {
+    let mut a = vhost(&["api.a.com"], "a-function");
+    a.prelude_detection = Some(PreludeDetection::Never);
+    let mut sse = route("/events", &[], "events-function");
+    sse.prelude_detection = Some(PreludeDetection::Always);
+    a.routes = vec![sse];
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        virtual_hosts: vec![a],
+        ..Config::default()
+    };
+    let router = HostRouter::new(&config).unwrap();
+
+    let prelude_detection = |host: &str, path: &str| match router.resolve(Some(host)).routes.find(&Method::GET, path) {
+        RouteLookup::Matched(m) => m.target.prelude_detection.clone(),
+        other => panic!("unexpected lookup result {:?}", other),
+    };
+    assert_eq!(prelude_detection("api.a.com", "/events"), PreludeDetection::Always);
+    assert_eq!(prelude_detection("api.a.com", "/"), PreludeDetection::Never);
+    assert_eq!(prelude_detection("api.b.com", "/"), PreludeDetection::ContentType);
+}
+
+#[test]
+fn test_route_table_stream_timeouts() {
+    let mut slow = route("/slow", &[], "slow-function");
+    slow.stream_idle_timeout = Some(60);
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        stream_idle_timeout: Some(5),
+        stream_max_duration: Some(300),
+        routes: vec![slow],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    let RouteLookup::Matched(m) = table.find(&Method::GET, "/slow") else {
+        panic!("expected a match");
+    };
+    assert_eq!(m.target.stream_idle_timeout, Some(Duration::from_secs(60)));
+    assert_eq!(m.target.stream_max_duration, Some(Duration::from_secs(300)));
+    let RouteLookup::Matched(m) = table.find(&Method::GET, "/other") else {
+        panic!("expected a match");
+    };
+    assert_eq!(m.target.stream_idle_timeout, Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_route_table_sse() {
+    let mut events = route("/events", &[], "events-function");
+    events.sse = Some(true);
+    events.lambda_invoke_mode = Some(LambdaInvokeMode::ResponseStream);
+    let mut quiet = route("/quiet", &[], "quiet-function");
+    quiet.sse = Some(true);
+    quiet.sse_keepalive_interval = Some(0);
+    quiet.lambda_invoke_mode = Some(LambdaInvokeMode::ResponseStream);
+    let config = Config {
+        routes: vec![events, quiet],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    let RouteLookup::Matched(m) = table.find(&Method::GET, "/events") else {
+        panic!("expected a match");
+    };
+    assert!(m.target.sse);
+    assert_eq!(m.target.sse_keepalive_interval, Some(Duration::from_secs(15)));
+    let RouteLookup::Matched(m) = table.find(&Method::GET, "/quiet") else {
+        panic!("expected a match");
+    };
+    assert_eq!(m.target.sse_keepalive_interval, None);
+}
+
+#[test]
+fn test_route_table_sse_requires_streaming() {
+    let mut events = route("/events", &[], "events-function");
+    events.sse = Some(true);
+    let config = Config {
+        routes: vec![events],
+        ..Config::default()
+    };
+    assert!(RouteTable::new(&config).is_err());
+}
+
+#[test]
+fn test_host_router_lambda_client() {
+    let mut a = vhost(&["api.a.com"], "a-function");
+    a.lambda_client.region = Some("eu-west-1".to_string());
+    let mut local = route("/local", &[], "local-function");
+    local.lambda_client.endpoint_url = Some("http://localhost:9001".to_string());
+    a.routes = vec![local];
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        lambda_client: LambdaClientConfig {
+            region: Some("us-east-1".to_string()),
+            max_attempts: Some(2),
+            ..Default::default()
+        },
+        virtual_hosts: vec![a],
+        ..Config::default()
+    };
+    let router = HostRouter::new(&config).unwrap();
+
+    let lambda_client = |host: &str, path: &str| match router.resolve(Some(host)).routes.find(&Method::GET, path) {
+        RouteLookup::Matched(m) => m.target.lambda_client.clone(),
+        other => panic!("unexpected lookup result {:?}", other),
+    };
+    let local = lambda_client("api.a.com", "/local");
+    assert_eq!(local.region.as_deref(), Some("eu-west-1"));
+    assert_eq!(local.endpoint_url.as_deref(), Some("http://localhost:9001"));
+    assert_eq!(local.max_attempts, Some(2));
+    assert_eq!(lambda_client("api.a.com", "/").endpoint_url, None);
+    assert_eq!(lambda_client("api.b.com", "/").region.as_deref(), Some("us-east-1"));
+    let distinct: HashSet<_> = router.targets().map(|t| &t.lambda_client).collect();
+    assert_eq!(distinct.len(), 3);
+}
+
+#[test]
+fn test_host_router_regions() {
+    let mut a = vhost(&["api.a.com"], "a-function");
+    a.regions = Some(vec!["eu-west-1".to_string(), "eu-central-1".to_string()]);
+    let mut single = route("/single", &[], "single-function");
+    single.regions = Some(Vec::new());
+    a.routes = vec![single];
+    let config = Config {
+        lambda_function_name: "default-function".to_string(),
+        regions: vec!["us-east-1".to_string(), "us-west-2".to_string()],
+        virtual_hosts: vec![a],
+        ..Config::default()
+    };
+    let router = HostRouter::new(&config).unwrap();
+
+    let regions = |host: &str, path: &str| match router.resolve(Some(host)).routes.find(&Method::GET, path) {
+        RouteLookup::Matched(m) => m.target.regions.clone(),
+        other => panic!("unexpected lookup result {:?}", other),
+    };
+    assert_eq!(regions("api.a.com", "/"), vec!["eu-west-1", "eu-central-1"]);
+    assert!(regions("api.a.com", "/single").is_empty());
+    assert_eq!(regions("api.b.com", "/"), vec!["us-east-1", "us-west-2"]);
+}
+
+#[test]
+fn test_route_table_duplicate_regions() {
+    let mut orders = route("/orders", &[], "orders-function");
+    orders.regions = Some(vec!["us-east-1".to_string(), "us-east-1".to_string()]);
+    let config = Config {
+        routes: vec![orders],
+        ..Config::default()
+    };
+    assert_eq!(
+        RouteTable::new(&config).unwrap_err(),
+        "Region us-east-1 listed more than once for route /orders"
+    );
+}
+
+#[test]
+fn test_route_table_variants() {
+    let mut orders = route("/orders", &[], "orders-function");
+    orders.variants = vec![
+        VariantConfig {
+            qualifier: "1".to_string(),
+            weight: 1,
+        },
+        VariantConfig {
+            qualifier: "2".to_string(),
+            weight: 0,
+        },
+    ];
+    let config = Config {
+        routes: vec![orders],
+        ..Config::default()
+    };
+    let table = RouteTable::new(&config).unwrap();
+
+    let RouteLookup::Matched(m) = table.find(&Method::GET, "/orders") else {
+        panic!("expected a match");
+    };
+    let variant = m.canary.unwrap().select(&axum::http::HeaderMap::new());
+    assert_eq!(variant.target.qualifier.as_deref(), Some("1"));
+    let qualifiers: Vec<_> = table.targets().map(|t| t.qualifier.as_deref()).collect();
+    assert_eq!(qualifiers, vec![None, Some("1"), Some("2")]);
+}
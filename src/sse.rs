@@ -0,0 +1,59 @@
+use bytes::Bytes;
+
+/// Comment sent on SSE responses while the function is silent.
+pub const KEEPALIVE: &[u8] = b":keepalive\n\n";
+
+/// Pending data above this size is flushed without waiting for the end of the event.
+const MAX_PENDING: usize = 1024 * 1024;
+
+/// Holds back streamed data until a Server-Sent Event is complete, so clients and
+/// intermediaries never receive part of an event.
+#[derive(Debug, Default)]
+pub struct EventBuffer {
+    pending: Vec<u8>,
+    /// Whether the data returned so far ends inside an event, after an oversized flush.
+    in_event: bool,
+}
+
+impl EventBuffer {
+    /// Adds streamed data and returns the events it completed, if any.
+    pub fn push(&mut self, data: &[u8]) -> Option<Bytes> {
+        // The pending data holds no boundary, but its last bytes may start one.
+        let searched = self.pending.len().saturating_sub(3);
+        self.pending.extend_from_slice(data);
+
+        let (end, in_event) = match event_boundary(&self.pending[searched..]) {
+            Some(end) => (searched + end, false),
+            None if self.pending.len() > MAX_PENDING => (self.pending.len(), true),
+            None => return None,
+        };
+        self.in_event = in_event;
+        let rest = self.pending.split_off(end);
+        Some(Bytes::from(std::mem::replace(&mut self.pending, rest)))
+    }
+
+    /// Whether the data returned so far ends on an event boundary, where comments such as
+    /// keepalives can be sent.
+    pub fn at_boundary(&self) -> bool {
+        !self.in_event
+    }
+
+    /// Returns the data of an incomplete last event, if any.
+    pub fn finish(&mut self) -> Option<Bytes> {
+        (!self.pending.is_empty()).then(|| Bytes::from(std::mem::take(&mut self.pending)))
+    }
+}
+
+/// The end of the last blank line in `data`, which terminates an event. Lines end with
+/// `\n`, `\r\n` or `\r`.
+fn event_boundary(data: &[u8]) -> Option<usize> {
+    (1..data.len()).rev().find_map(|i| {
+        let head = &data[..=i];
+        (head.ends_with(b"\n\n") || head.ends_with(b"\r\r") || head.ends_with(b"\r\n\r\n")).then_some(i + 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    include!("sse_tests.rs");
+}
@@ -0,0 +1,50 @@
+use super::*;
+
+#[test]
+fn test_event_buffer_complete_events() {
+    let mut events = EventBuffer::default();
+
+    assert_eq!(events.push(b"data: one\n\ndata: two\n\n").unwrap(), "data: one\n\ndata: two\n\n");
+    assert!(events.finish().is_none());
+}
+
+#[test]
+fn test_event_buffer_split_event() {
+    let mut events = EventBuffer::default();
+
+    assert!(events.push(b"id: 1\ndata: hel").is_none());
+    assert_eq!(events.push(b"lo\n\ndata: wor").unwrap(), "id: 1\ndata: hello\n\n");
+    assert!(events.push(b"ld\n").is_none());
+    assert_eq!(events.push(b"\n").unwrap(), "data: world\n\n");
+}
+
+#[test]
+fn test_event_buffer_crlf() {
+    let mut events = EventBuffer::default();
+
+    assert!(events.push(b"data: one\r\n\r").is_none());
+    assert_eq!(events.push(b"\n").unwrap(), "data: one\r\n\r\n");
+    assert_eq!(events.push(b"data: two\r\r").unwrap(), "data: two\r\r");
+}
+
+#[test]
+fn test_event_buffer_finish() {
+    let mut events = EventBuffer::default();
+
+    assert!(events.push(b"data: partial").is_none());
+    assert_eq!(events.finish().unwrap(), "data: partial");
+    assert!(events.finish().is_none());
+}
+
+#[test]
+fn test_event_buffer_flushes_oversized_event() {
+    let mut events = EventBuffer::default();
+
+    let data = vec![b'a'; MAX_PENDING + 1];
+    assert_eq!(events.push(&data).unwrap().len(), MAX_PENDING + 1);
+    assert!(!events.at_boundary());
+    assert!(events.push(b"b").is_none());
+    assert!(!events.at_boundary());
+    assert_eq!(events.push(b"\n\n").unwrap(), "b\n\n");
+    assert!(events.at_boundary());
+}
@@ -0,0 +1,68 @@
+use minijinja::Environment;
+use serde::Serialize;
+
+const REQUEST_TEMPLATE: &str = "request";
+const RESPONSE_TEMPLATE: &str = "response";
+
+/// Compiled request and response templates of the `Template` payload format.
+///
+/// The request template renders the event sent to the function. The response template renders
+/// a JSON object with `statusCode`, `headers` and `body` from the function result.
+#[derive(Debug)]
+pub struct Templates {
+    env: Environment<'static>,
+    request_source: Option<String>,
+    response_source: Option<String>,
+}
+
+impl Templates {
+    pub fn new(request: Option<&str>, response: Option<&str>) -> Result<Self, String> {
+        let mut env = Environment::new();
+        if let Some(source) = request {
+            env.add_template_owned(REQUEST_TEMPLATE, source.to_string())
+                .map_err(|e| format!("Invalid request_template: {}", e))?;
+        }
+        if let Some(source) = response {
+            env.add_template_owned(RESPONSE_TEMPLATE, source.to_string())
+                .map_err(|e| format!("Invalid response_template: {}", e))?;
+        }
+
+        Ok(Templates {
+            env,
+            request_source: request.map(String::from),
+            response_source: response.map(String::from),
+        })
+    }
+
+    pub fn request_source(&self) -> Option<&str> {
+        self.request_source.as_deref()
+    }
+
+    pub fn response_source(&self) -> Option<&str> {
+        self.response_source.as_deref()
+    }
+
+    pub fn has_response(&self) -> bool {
+        self.response_source.is_some()
+    }
+
+    pub fn render_request<S: Serialize>(&self, context: S) -> Result<String, String> {
+        self.render(REQUEST_TEMPLATE, context)
+    }
+
+    pub fn render_response<S: Serialize>(&self, context: S) -> Result<String, String> {
+        self.render(RESPONSE_TEMPLATE, context)
+    }
+
+    fn render<S: Serialize>(&self, name: &str, context: S) -> Result<String, String> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|e| format!("Failed to render {} template: {}", name, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!("template_tests.rs");
+}
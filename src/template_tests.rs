@@ -0,0 +1,31 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn test_templates_render() {
+    let templates = Templates::new(
+        Some(r#"{"user": {{ user|tojson }}}"#),
+        Some(r#"{"statusCode": {{ status }}, "body": {{ body|tojson }}}"#),
+    )
+    .unwrap();
+
+    assert_eq!(templates.render_request(json!({"user": "a\"b"})).unwrap(), r#"{"user": "a\"b"}"#);
+    assert_eq!(
+        templates.render_response(json!({"status": 201, "body": "ok"})).unwrap(),
+        r#"{"statusCode": 201, "body": "ok"}"#
+    );
+    assert!(templates.has_response());
+}
+
+#[test]
+fn test_templates_invalid_syntax() {
+    assert!(Templates::new(Some("{{ unclosed"), None).is_err());
+    assert!(Templates::new(None, Some("{% if %}")).is_err());
+}
+
+#[test]
+fn test_templates_missing_template() {
+    let templates = Templates::new(None, None).unwrap();
+    assert!(templates.render_request(json!({})).is_err());
+    assert!(!templates.has_response());
+}